@@ -0,0 +1,337 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time;
+use crate::types::ToTamariArg;
+use crate::connection::{FrameBuffer, READ_CHUNK_SIZE};
+use crate::connection_info::{ConnectionAddr, ConnectionInfo};
+use crate::error::{ErrorKind, TamariError};
+use crate::parser::{self, Response};
+use crate::protocol::{self, Request};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// The non-blocking counterpart of [`Connection`](crate::Connection).
+///
+/// Methods return boxed futures so that connections can be used as trait
+/// objects, the same way [`Client`](crate::Client) holds a
+/// `Box<dyn Connection>`.
+pub trait AsyncConnection: Send {
+    fn read(&mut self) -> BoxFuture<'_, Result<Vec<u8>, TamariError>>;
+    fn write<'a>(&'a mut self, buffer: &'a [u8]) -> BoxFuture<'a, Result<(), TamariError>>;
+}
+
+/// A TCP connection driven by the tokio runtime.
+///
+/// Like [`TcpConnection`](crate::TcpConnection), it can't be used any more
+/// once a read or write has timed out.
+pub struct AsyncTcpConnection {
+    stream: TcpStream,
+    frames: FrameBuffer,
+    timeout: Option<Duration>,
+}
+
+impl AsyncTcpConnection {
+    pub async fn new(addr: &str, port: u16) -> Result<Self, TamariError> {
+        match TcpStream::connect((addr, port)).await {
+            Ok(stream) => Ok(AsyncTcpConnection::from_stream(stream)),
+            Err(e) => Err(TamariError::from(e)),
+        }
+    }
+
+    /// Connects to the server described by a `tamari://` URL. Any password in
+    /// the URL is ignored here; use `AsyncClient::from_url` to authenticate as
+    /// well.
+    pub async fn from_url(url: &str) -> Result<Self, TamariError> {
+        AsyncTcpConnection::from_info(&ConnectionInfo::from_url(url)?).await
+    }
+
+    pub async fn from_info(info: &ConnectionInfo) -> Result<Self, TamariError> {
+        let (addr, port) = match info.addr {
+            ConnectionAddr::Tcp(_, _) if info.tls.is_some() => {
+                return Err(TamariError::new(ErrorKind::InvalidUrl(String::from("not a plain TCP address"))));
+            },
+            ConnectionAddr::Tcp(ref addr, port) => (addr, port),
+            ConnectionAddr::Unix(_) => {
+                return Err(TamariError::new(ErrorKind::InvalidUrl(String::from("not a TCP address"))));
+            },
+        };
+
+        let mut connection = match info.connect_timeout.or(info.timeout) {
+            Some(timeout) => with_timeout(Some(timeout), AsyncTcpConnection::new(addr, port)).await?,
+            None => AsyncTcpConnection::new(addr, port).await?,
+        };
+
+        connection.timeout = info.timeout;
+
+        Ok(connection)
+    }
+
+    /// Bounds the time a single read or write may take.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    fn from_stream(stream: TcpStream) -> Self {
+        // requests are small and latency bound, see TcpConnectionBuilder
+        let _ = stream.set_nodelay(true);
+
+        AsyncTcpConnection { stream, frames: FrameBuffer::new(), timeout: None }
+    }
+
+    async fn read_frame(&mut self) -> Result<Vec<u8>, TamariError> {
+        let mut chunk = [0; READ_CHUNK_SIZE];
+
+        loop {
+            if let Some(frame) = self.frames.next_frame()? {
+                return Ok(frame);
+            }
+
+            let read = self.stream.read(&mut chunk).await;
+            self.frames.fill(read, &chunk)?;
+        }
+    }
+}
+
+impl AsyncConnection for AsyncTcpConnection {
+    fn read(&mut self) -> BoxFuture<'_, Result<Vec<u8>, TamariError>> {
+        Box::pin(async move {
+            self.frames.check()?;
+
+            let timeout = self.timeout;
+            let result = with_timeout(timeout, self.read_frame()).await;
+            self.frames.track(result)
+        })
+    }
+
+    fn write<'a>(&'a mut self, buffer: &'a [u8]) -> BoxFuture<'a, Result<(), TamariError>> {
+        Box::pin(async move {
+            self.frames.check()?;
+
+            let timeout = self.timeout;
+            let write = async {
+                match self.stream.write_all(buffer).await {
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(TamariError::from(e)),
+                }
+            };
+
+            let result = with_timeout(timeout, write).await;
+            self.frames.track(result)
+        })
+    }
+}
+
+async fn with_timeout<T, F>(timeout: Option<Duration>, future: F) -> Result<T, TamariError>
+where
+    F: Future<Output = Result<T, TamariError>>,
+{
+    match timeout {
+        Some(timeout) => match time::timeout(timeout, future).await {
+            Ok(result) => result,
+            Err(_) => Err(TamariError::new(ErrorKind::Timeout(io::Error::new(io::ErrorKind::TimedOut, "operation timed out")))),
+        },
+        None => future.await,
+    }
+}
+
+/// The non-blocking counterpart of [`Client`](crate::Client).
+///
+/// A request whose future is dropped before its reply is read, such as one
+/// cancelled by `tokio::time::timeout`, leaves the reply on the connection to
+/// be taken for the answer to the next request. Every later request fails
+/// with an `ErrorKind::IO` error instead, and the client has to be replaced.
+pub struct AsyncClient {
+    connection: Box<dyn AsyncConnection>,
+    // set while a request waits for its reply, so still set if it was cancelled
+    in_flight: bool,
+}
+
+impl AsyncClient {
+    pub fn new(connection: Box<dyn AsyncConnection>) -> Self {
+        AsyncClient { connection, in_flight: false }
+    }
+
+    /// Connects to the server described by a `tamari://` URL, authenticating
+    /// with the URL's password if it has one.
+    pub async fn from_url(url: &str) -> Result<Self, TamariError> {
+        let info = ConnectionInfo::from_url(url)?;
+        let mut client = AsyncClient::new(Box::new(AsyncTcpConnection::from_info(&info).await?));
+
+        if let Some(ref password) = info.password {
+            client.authenticate(password).await?;
+        }
+
+        Ok(client)
+    }
+
+    /// Sends `password` to the server. A rejected password is reported as an
+    /// `ErrorKind::Auth` error carrying the server's message.
+    pub async fn authenticate<P: ToTamariArg>(&mut self, password: P) -> Result<(), TamariError> {
+        let password = password.to_tamari_arg();
+
+        match self.request(&Request::Auth(password)).await? {
+            Response::Success | Response::SuccessWithResult(_) | Response::Multi(_) => Ok(()),
+            Response::Error(msg) => Err(TamariError::new(ErrorKind::Auth(String::from_utf8_lossy(&msg).into_owned()))),
+            Response::NotFound => Err(TamariError::new(ErrorKind::Auth(String::from("unexpected not found response")))),
+        }
+    }
+
+    pub async fn get<K: ToTamariArg>(&mut self, key: K) -> Result<Response, TamariError> {
+        let key = key.to_tamari_arg();
+
+        self.request(&Request::Get(key)).await
+    }
+
+    pub async fn set<K: ToTamariArg, V: ToTamariArg>(&mut self, key: K, value: V) -> Result<Response, TamariError> {
+        let key = key.to_tamari_arg();
+        let value = value.to_tamari_arg();
+
+        self.request(&Request::Set(key, value)).await
+    }
+
+    pub async fn delete<K: ToTamariArg>(&mut self, key: K) -> Result<Response, TamariError> {
+        let key = key.to_tamari_arg();
+
+        self.request(&Request::Delete(key)).await
+    }
+
+    async fn request(&mut self, request: &Request) -> Result<Response, TamariError> {
+        if self.in_flight {
+            return Err(TamariError::new(ErrorKind::IO(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "connection is unusable after an unfinished request",
+            ))));
+        }
+
+        self.in_flight = true;
+        self.connection.write(&protocol::encode(request)).await?;
+
+        let resp_raw = self.connection.read().await?;
+        self.in_flight = false;
+
+        match parser::parse_response(&resp_raw) {
+            Ok(resp) => Ok(resp),
+            Err(e) => Err(TamariError::new(ErrorKind::Parse(e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net;
+    use std::thread;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn get_over_tcp() {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 7];
+            stream.read_exact(&mut request).unwrap();
+            assert_eq!(&request, b"=3\tfoo\n");
+
+            stream.write_all(b"$7\tbar").unwrap();
+            thread::sleep(Duration::from_millis(10));
+            stream.write_all(b"\nbaz\n").unwrap();
+        });
+
+        let response = block_on(async {
+            let mut client = AsyncClient::from_url(&format!("tamari://{}", addr)).await?;
+            client.get("foo").await
+        });
+
+        assert_eq!(response.unwrap(), Response::SuccessWithResult(b"bar\nbaz".to_vec()));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn read_timeout() {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let response = block_on(async {
+            let mut client = AsyncClient::from_url(&format!("tamari://{}/?timeout=0.05", addr)).await?;
+            client.get("foo").await
+        });
+
+        assert!(matches!(response.unwrap_err().kind(), ErrorKind::Timeout(_)));
+        drop(listener);
+    }
+
+    #[test]
+    fn unusable_after_cancelled_request() {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 5];
+            stream.read_exact(&mut request).unwrap();
+            assert_eq!(&request, b"=1\ta\n");
+
+            // answers after the client gave up waiting
+            thread::sleep(Duration::from_millis(50));
+            stream.write_all(b"$1\tA\n").unwrap();
+
+            // closing with the reply unread may reset the connection
+            let mut rest = Vec::new();
+            let _ = stream.read_to_end(&mut rest);
+            rest
+        });
+
+        let responses = block_on(async {
+            let mut client = AsyncClient::from_url(&format!("tamari://{}", addr)).await.unwrap();
+            let first = time::timeout(Duration::from_millis(20), client.get("a")).await;
+            time::sleep(Duration::from_millis(100)).await;
+            (first.is_err(), client.get("b").await)
+        });
+
+        assert!(responses.0);
+        assert!(matches!(responses.1.unwrap_err().kind(), ErrorKind::IO(e) if e.kind() == io::ErrorKind::NotConnected));
+        // the second request was never sent
+        assert_eq!(handle.join().unwrap(), b"");
+    }
+
+    #[test]
+    fn unusable_after_timeout() {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 7];
+            stream.read_exact(&mut request).unwrap();
+
+            // answers too late, when the client may have moved on
+            thread::sleep(Duration::from_millis(100));
+            stream.write_all(b"$3\tbar\n").unwrap();
+        });
+
+        let responses = block_on(async {
+            let mut client = AsyncClient::from_url(&format!("tamari://{}/?timeout=0.05", addr)).await.unwrap();
+            let first = client.get("foo").await;
+            time::sleep(Duration::from_millis(100)).await;
+            (first, client.get("baz").await)
+        });
+
+        assert!(matches!(responses.0.unwrap_err().kind(), ErrorKind::Timeout(_)));
+        assert!(matches!(responses.1.unwrap_err().kind(), ErrorKind::IO(e) if e.kind() == io::ErrorKind::NotConnected));
+        handle.join().unwrap();
+    }
+}
@@ -1,127 +1,448 @@
-use crate::error::{ErrorKind, TamariError};
+use crate::error::{ErrorKind, ServerError, TamariError};
 use crate::parser;
-use crate::connection::Connection;
+use crate::protocol::{self, Request};
+use crate::connection::{self, Connection};
+use crate::connection_info::ConnectionInfo;
+use crate::layer::Layer;
+use crate::metrics::Metrics;
+use crate::retry::RetryPolicy;
+use crate::types::{FromTamariValue, ToTamariArg};
+#[cfg(feature = "serde")]
+use crate::codec::Codec;
+use std::io;
+use std::mem;
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+type ConnectionFactory = Box<dyn FnMut() -> Result<Box<dyn Connection>, TamariError> + Send>;
 
 pub struct Client {
     connection: Box<dyn Connection>,
-} 
+    reconnect: Option<ConnectionFactory>,
+    retry_policy: RetryPolicy,
+    // kept so a rebuilt connection can be authenticated again
+    password: Option<Vec<u8>>,
+    strict_errors: bool,
+    // applied again to every rebuilt connection
+    layers: Vec<Arc<dyn Layer>>,
+    metrics: Option<Metrics>,
+}
 
 pub use parser::Response;
 
 impl Client {
     pub fn new(connection: Box<dyn Connection>) -> Self {
-        Client { connection: connection }
+        Client { connection, reconnect: None, retry_policy: RetryPolicy::none(), password: None, strict_errors: false, layers: Vec::new(), metrics: None }
     }
 
-    pub fn get<K: ToTamariArg> (&mut self, key: K) -> Result<Response, TamariError> {
-        let mut request: Vec<u8> = b"=".to_vec();
-        let key = key.write_tamari_arg();
-        let key_len = key.len();
-        // this could be better
-        request.append(&mut key_len.to_string().as_bytes().to_vec());
-        request.append(&mut b"\t".to_vec());
-        request.append(&mut key.to_vec());
-        request.append(&mut b"\n".to_vec());
-
-        self.connection.write(&request)?;
-
-        match self.connection.read() {
-            Ok(resp_raw) => {
-                match parser::parse_response(&resp_raw) {
-                    Ok(resp) => return Ok(resp),
-                    Err(e) => return Err(TamariError::new(ErrorKind::Parse(e))),
-                }
-            },
-            Err(e) => return Err(e) ,
+    /// Connects to the server described by a connection URL, authenticating
+    /// with the URL's password if it has one. The client is set up to reconnect
+    /// to the same URL, though it won't retry until given a retry policy.
+    pub fn from_url(url: &str) -> Result<Self, TamariError> {
+        let info = ConnectionInfo::from_url(url)?;
+        let connection = connection::connect(&info)?;
+        let password = info.password.clone();
+
+        let mut client = Client::new(connection);
+        client.set_reconnect(move || connection::connect(&info));
+
+        if let Some(password) = password {
+            client.authenticate(password)?;
         }
+
+        Ok(client)
     }
 
-    pub fn set<K: ToTamariArg, V:ToTamariArg> (&mut self, key: K, value: V) -> Result<Response, TamariError> {
-        let mut key = key.write_tamari_arg().to_vec();
-        let mut key_len = key.len().to_string().as_bytes().to_vec();
-        let mut value = value.write_tamari_arg().to_vec();
-        let mut value_len = value.len().to_string().as_bytes().to_vec();
-
-        let mut request: Vec<u8> = b"+".to_vec();
-
-        request.append(&mut key_len);
-        request.append(&mut b"\t".to_vec());
-        request.append(&mut key);
-        request.append(&mut value_len);
-        request.append(&mut b"\t".to_vec());
-        request.append(&mut value);
-        request.append(&mut b"\n".to_vec());
-
-        self.connection.write(&request)?;
-
-        match self.connection.read() {
-            Ok(resp_raw) => {
-                match parser::parse_response(&resp_raw) {
-                    Ok(resp) => return Ok(resp),
-                    Err(e) => return Err(TamariError::new(ErrorKind::Parse(e))),
-                }
+    /// Sets the factory used to rebuild the connection after it fails.
+    pub fn set_reconnect<F>(&mut self, factory: F)
+    where
+        F: FnMut() -> Result<Box<dyn Connection>, TamariError> + Send + 'static,
+    {
+        self.reconnect = Some(Box::new(factory));
+    }
+
+    /// Sets how requests are retried after connection failures. Retrying needs
+    /// a reconnect factory, see [`Client::set_reconnect`].
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    /// Turns `!` responses into `ErrorKind::Server` errors instead of returning
+    /// them as `Response::Error`, so that `?` stops at server errors too.
+    pub fn set_strict_errors(&mut self, strict: bool) {
+        self.strict_errors = strict;
+    }
+
+    /// Records the requests, errors and latencies of gets, sets and deletes in
+    /// `metrics`, including those sent through pipelines.
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        self.metrics = Some(metrics);
+    }
+
+    pub fn metrics(&self) -> Option<&Metrics> {
+        self.metrics.as_ref()
+    }
+
+    /// Wraps the connection in `layer`, and every connection the client
+    /// reconnects with after it. Layers added later wrap the earlier ones, so
+    /// they see requests first and replies last.
+    pub fn add_layer<L: Layer + 'static>(&mut self, layer: L) {
+        let connection = mem::replace(&mut self.connection, Box::new(Detached));
+        self.connection = layer.layer(connection);
+        self.layers.push(Arc::new(layer));
+    }
+
+    /// Replaces the connection with a fresh one from the reconnect factory,
+    /// authenticating it again if the client had authenticated before.
+    pub fn reconnect(&mut self) -> Result<(), TamariError> {
+        let factory = match self.reconnect {
+            Some(ref mut factory) => factory,
+            None => return Err(TamariError::new(ErrorKind::IO(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "client has no reconnect factory",
+            )))),
+        };
+
+        self.connection = self.layers.iter().fold(factory()?, |connection, layer| layer.layer(connection));
+
+        match self.password.clone() {
+            Some(password) => self.authenticate(&password[..]),
+            None => Ok(()),
+        }
+    }
+
+    /// Creates a client and authenticates it with `password` before handing
+    /// it back.
+    pub fn with_password<P: ToTamariArg>(connection: Box<dyn Connection>, password: P) -> Result<Self, TamariError> {
+        let mut client = Client::new(connection);
+        client.authenticate(password)?;
+
+        Ok(client)
+    }
+
+    /// Sends `password` to the server. A rejected password is reported as an
+    /// `ErrorKind::Auth` error carrying the server's message.
+    pub fn authenticate<P: ToTamariArg>(&mut self, password: P) -> Result<(), TamariError> {
+        let password = password.to_tamari_arg();
+
+        match self.request(&Request::Auth(password.clone()))? {
+            Response::Success | Response::SuccessWithResult(_) | Response::Multi(_) => {
+                self.password = Some(password);
+                Ok(())
             },
-            Err(e) => return Err(e) ,
+            Response::Error(msg) => Err(TamariError::new(ErrorKind::Auth(String::from_utf8_lossy(&msg).into_owned()))),
+            Response::NotFound => Err(TamariError::new(ErrorKind::Auth(String::from("unexpected not found response")))),
         }
     }
 
+    pub fn get<K: ToTamariArg> (&mut self, key: K) -> Result<Response, TamariError> {
+        let key = key.to_tamari_arg();
+
+        self.request(&Request::Get(key))
+    }
+
+    /// Gets the value at `key`, or `None` if the key doesn't exist. An empty
+    /// value comes back as `Some` of an empty vector.
+    pub fn get_opt<K: ToTamariArg>(&mut self, key: K) -> Result<Option<Vec<u8>>, TamariError> {
+        self.get_as(key)
+    }
+
+    /// Gets the value at `key` converted to `T`, e.g.
+    /// `client.get_as::<u64>("counter")`. Use an `Option<T>` to allow for the
+    /// key not existing.
+    pub fn get_as<T: FromTamariValue>(&mut self, key: impl ToTamariArg) -> Result<T, TamariError> {
+        T::from_tamari_value(self.get(key)?)
+    }
+
+    pub fn set<K: ToTamariArg, V:ToTamariArg> (&mut self, key: K, value: V) -> Result<Response, TamariError> {
+        let key = key.to_tamari_arg();
+        let value = value.to_tamari_arg();
+
+        self.request(&Request::Set(key, value))
+    }
+
     pub fn delete<K: ToTamariArg> (&mut self, key: K) -> Result<Response, TamariError> {
-        let mut key = key.write_tamari_arg().to_vec();
-        let mut key_len = key.len().to_string().as_bytes().to_vec();
+        let key = key.to_tamari_arg();
+
+        self.request(&Request::Delete(key))
+    }
+
+    /// Encodes `value` with `codec` and sets it at `key`, e.g.
+    /// `client.set_serialized(&Json, "user:1", &user)`.
+    #[cfg(feature = "serde")]
+    pub fn set_serialized<V>(&mut self, codec: &impl Codec, key: impl ToTamariArg, value: &V) -> Result<Response, TamariError>
+    where
+        V: serde::Serialize + ?Sized,
+    {
+        let value = codec.encode(value)?;
+
+        self.set(key, value)
+    }
+
+    /// Gets the value at `key` and decodes it with `codec`, e.g.
+    /// `client.get_deserialized::<User>(&Json, "user:1")`. Returns `None` if
+    /// the key doesn't exist.
+    #[cfg(feature = "serde")]
+    pub fn get_deserialized<T>(&mut self, codec: &impl Codec, key: impl ToTamariArg) -> Result<Option<T>, TamariError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        match self.get_opt(key)? {
+            Some(value) => codec.decode(&value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Gets the values at many keys, pipelined into a single write. Results are
+    /// returned in the same order as the keys.
+    pub fn mget<I, K>(&mut self, keys: I) -> Vec<Result<Response, TamariError>>
+    where
+        I: IntoIterator<Item = K>,
+        K: ToTamariArg,
+    {
+        let mut pipeline = self.pipeline();
+
+        for key in keys {
+            pipeline.get(key);
+        }
+
+        pipeline.execute()
+    }
+
+    /// Sets many key/value pairs, pipelined into a single write. Results are
+    /// returned in the same order as the pairs.
+    pub fn mset<I, K, V>(&mut self, pairs: I) -> Vec<Result<Response, TamariError>>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: ToTamariArg,
+        V: ToTamariArg,
+    {
+        let mut pipeline = self.pipeline();
+
+        for (key, value) in pairs {
+            pipeline.set(key, value);
+        }
+
+        pipeline.execute()
+    }
+
+    /// Deletes many keys, pipelined into a single write. Results are returned
+    /// in the same order as the keys.
+    pub fn mdel<I, K>(&mut self, keys: I) -> Vec<Result<Response, TamariError>>
+    where
+        I: IntoIterator<Item = K>,
+        K: ToTamariArg,
+    {
+        let mut pipeline = self.pipeline();
+
+        for key in keys {
+            pipeline.delete(key);
+        }
+
+        pipeline.execute()
+    }
+
+    /// Starts a pipeline that sends many requests in a single write.
+    pub fn pipeline(&mut self) -> Pipeline<'_> {
+        Pipeline { client: self, requests: Vec::new() }
+    }
 
-        let mut request: Vec<u8> = b"-".to_vec();
+    fn request(&mut self, request: &Request) -> Result<Response, TamariError> {
+        let retryable = self.reconnect.is_some() && match request {
+            Request::Get(_) | Request::Delete(_) => true,
+            Request::Set(_, _) => self.retry_policy.retries_sets(),
+            Request::Auth(_) => false,
+        };
+
+        let started = Instant::now();
+        let mut result = self.request_once(request);
+        let mut attempt = 1;
+
+        while retryable && attempt < self.retry_policy.max_attempts() && is_connection_error(&result) {
+            thread::sleep(self.retry_policy.backoff(attempt));
+            attempt += 1;
+
+            result = self.reconnect().and_then(|_| self.request_once(request));
+        }
 
-        request.append(&mut key_len);
-        request.append(&mut b"\t".to_vec());
-        request.append(&mut key);
-        request.append(&mut b"\n".to_vec());
+        let result = match request {
+            // authenticate reports rejected passwords itself
+            Request::Auth(_) => result,
+            _ => self.check_response(result),
+        };
+
+        self.record(request, started, &result);
+
+        result
+    }
 
-        self.connection.write(&request)?;
+    fn request_once(&mut self, request: &Request) -> Result<Response, TamariError> {
+        self.connection.write(&protocol::encode(request))?;
 
-        match self.connection.read() {
-            Ok(resp_raw) => {
-                match parser::parse_response(&resp_raw) {
-                    Ok(resp) => return Ok(resp),
-                    Err(e) => return Err(TamariError::new(ErrorKind::Parse(e))),
+        self.read_response()
+    }
+
+    fn request_batch(&mut self, requests: &[Request]) -> Vec<Result<Response, TamariError>> {
+        let mut responses = Vec::with_capacity(requests.len());
+
+        if requests.is_empty() {
+            return responses;
+        }
+
+        let mut buffer = Vec::new();
+        for request in requests {
+            protocol::encode_into(request, &mut buffer);
+        }
+
+        let started = Instant::now();
+
+        match self.connection.write(&buffer) {
+            Ok(_) => {
+                while responses.len() < requests.len() {
+                    // a reply that was framed but can't be parsed only fails
+                    // its own entry, any failure to read one fails the rest
+                    let (response, failed) = match self.connection.read() {
+                        Ok(raw) => (parse_reply(&raw), false),
+                        Err(e) => (Err(e), true),
+                    };
+
+                    let response = self.check_response(response);
+                    self.record(&requests[responses.len()], started, &response);
+                    responses.push(response);
+
+                    // once the connection itself has failed the remaining responses are lost
+                    if failed {
+                        break;
+                    }
                 }
             },
-            Err(e) => return Err(e) ,
+            Err(e) => {
+                let response = Err(e);
+                self.record(&requests[0], started, &response);
+                responses.push(response);
+            },
+        }
+
+        while responses.len() < requests.len() {
+            let response = Err(TamariError::new(ErrorKind::IO(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "pipeline aborted by an earlier connection error",
+            ))));
+            self.record(&requests[responses.len()], started, &response);
+            responses.push(response);
+        }
+
+        responses
+    }
+
+    fn record(&self, request: &Request, started: Instant, result: &Result<Response, TamariError>) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.record(request, started.elapsed(), result);
+        }
+    }
+
+    fn check_response(&self, result: Result<Response, TamariError>) -> Result<Response, TamariError> {
+        match result {
+            Ok(Response::Error(msg)) if self.strict_errors => Err(TamariError::new(ErrorKind::Server(ServerError::parse(&msg)))),
+            result => result,
         }
     }
-}
 
-pub trait ToTamariArg: Sized {
-    fn write_tamari_arg(&self) -> &[u8];
+    fn read_response(&mut self) -> Result<Response, TamariError> {
+        let resp_raw = self.connection.read()?;
+
+        parse_reply(&resp_raw)
+    }
 }
 
-impl ToTamariArg for String {
-    fn write_tamari_arg(&self) -> &[u8] {
-        self.as_bytes()
+fn parse_reply(raw: &[u8]) -> Result<Response, TamariError> {
+    match parser::parse_response(raw) {
+        Ok(resp) => Ok(resp),
+        Err(e) => Err(TamariError::new(ErrorKind::Parse(e))),
     }
 }
 
-impl<'a> ToTamariArg for &'a String {
-    fn write_tamari_arg(&self) -> &[u8] {
-        self.as_bytes()
+// Stands in for the connection while a layer is wrapped around it.
+struct Detached;
+
+impl Connection for Detached {
+    fn read(&mut self) -> Result<Vec<u8>, TamariError> {
+        Err(TamariError::new(ErrorKind::IO(io::ErrorKind::NotConnected.into())))
+    }
+
+    fn write(&mut self, _buffer: &[u8]) -> Result<(), TamariError> {
+        Err(TamariError::new(ErrorKind::IO(io::ErrorKind::NotConnected.into())))
     }
 }
 
-impl<'a> ToTamariArg for &'a str {
-    fn write_tamari_arg(&self) -> &[u8] {
-        self.as_bytes()
+fn is_connection_error<T>(result: &Result<T, TamariError>) -> bool {
+    match result {
+        Err(e) => matches!(e.kind(), ErrorKind::IO(_) | ErrorKind::Timeout(_)),
+        Ok(_) => false,
     }
 }
 
-impl<'a> ToTamariArg for &'a[u8] {
-    fn write_tamari_arg(&self) -> &[u8] {
+/// A batch of requests queued on a [`Client`].
+///
+/// Requests are buffered until [`Pipeline::execute`] writes them to the
+/// connection in one go and then reads back one response per request.
+pub struct Pipeline<'a> {
+    client: &'a mut Client,
+    requests: Vec<Request>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn get<K: ToTamariArg>(&mut self, key: K) -> &mut Self {
+        let key = key.to_tamari_arg();
+
+        self.requests.push(Request::Get(key));
         self
     }
-}
 
+    pub fn set<K: ToTamariArg, V: ToTamariArg>(&mut self, key: K, value: V) -> &mut Self {
+        let key = key.to_tamari_arg();
+        let value = value.to_tamari_arg();
+
+        self.requests.push(Request::Set(key, value));
+        self
+    }
+
+    pub fn delete<K: ToTamariArg>(&mut self, key: K) -> &mut Self {
+        let key = key.to_tamari_arg();
+
+        self.requests.push(Request::Delete(key));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// Sends every queued request and returns their responses in the order the
+    /// requests were queued. The pipeline is empty afterwards and can be reused.
+    ///
+    /// A reply that is framed correctly but can't be parsed, such as `!\n`,
+    /// only fails its own entry with a `Parse` error. If the connection fails
+    /// part way through, or a reply can't even be framed, the failing entry
+    /// holds the error and every entry after it holds a `ConnectionAborted` IO
+    /// error. Pipelines are never retried.
+    pub fn execute(&mut self) -> Vec<Result<Response, TamariError>> {
+        let requests = std::mem::take(&mut self.requests);
+
+        self.client.request_batch(&requests)
+    }
+}
 
+#[cfg(test)]
 mod tests {
     use super::*;
-    use std::io;
+    use std::collections::VecDeque;
+    use crate::metrics::Op;
 
     struct MockConnection<'a> {
         pub expected_write: &'a[u8],
@@ -137,7 +458,29 @@ mod tests {
            if buffer == self.expected_write {
                 Ok(())
             } else {
-                Err(TamariError::new(ErrorKind::IO(io::Error::new(io::ErrorKind::Other, "invalid write"))))
+                Err(TamariError::new(ErrorKind::IO(io::Error::other("invalid write"))))
+            }
+        }
+    }
+
+    struct QueueConnection {
+        pub expected_write: Vec<u8>,
+        pub reads: VecDeque<Result<Vec<u8>, TamariError>>,
+    }
+
+    impl Connection for QueueConnection {
+        fn read(&mut self) -> Result<Vec<u8>, TamariError> {
+            match self.reads.pop_front() {
+                Some(read) => read,
+                None => Err(TamariError::new(ErrorKind::IO(io::Error::new(io::ErrorKind::UnexpectedEof, "no more reads")))),
+            }
+        }
+
+        fn write(&mut self, buffer: &[u8]) -> Result<(), TamariError> {
+            if buffer == &self.expected_write[..] {
+                Ok(())
+            } else {
+                Err(TamariError::new(ErrorKind::IO(io::Error::other("invalid write"))))
             }
         }
     }
@@ -181,6 +524,90 @@ mod tests {
         assert!(matches!(response.unwrap_err(), _expected));
     }
 
+    #[test]
+    fn get_as_success() {
+        let connection = MockConnection {
+            expected_write: b"=7\tcounter\n",
+            expected_read: b"$2\t42\n",
+        };
+
+        let mut client = Client::new(Box::new(connection));
+
+        assert_eq!(client.get_as::<u64>("counter").unwrap(), 42);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialized_round_trip() {
+        use crate::codec::Json;
+
+        let connection = MockConnection {
+            expected_write: b"+3\tfoo7\t[1,2,3]\n",
+            expected_read: b"$\n",
+        };
+        let mut client = Client::new(Box::new(connection));
+        assert_eq!(client.set_serialized(&Json, "foo", &[1, 2, 3]).unwrap(), Response::Success);
+
+        let connection = MockConnection {
+            expected_write: b"=3\tfoo\n",
+            expected_read: b"$7\t[1,2,3]\n",
+        };
+        let mut client = Client::new(Box::new(connection));
+        assert_eq!(client.get_deserialized::<Vec<u32>>(&Json, "foo").unwrap(), Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn strict_errors() {
+        let connection = QueueConnection {
+            expected_write: b"=3\tfoo\n".to_vec(),
+            reads: VecDeque::from(vec![Ok(b"!20\tNOTFOUND no such key\n".to_vec()), Ok(b"!4\toops\n".to_vec())]),
+        };
+
+        let mut client = Client::new(Box::new(connection));
+        client.set_strict_errors(true);
+
+        let err = client.get("foo").unwrap_err();
+        assert!(err.is_not_found());
+
+        let results = client.mget(vec!["foo"]);
+        assert!(matches!(results[0], Err(ref e) if matches!(e.kind(), ErrorKind::Server(e) if e.code() == "ERR")));
+    }
+
+    #[test]
+    fn records_metrics() {
+        let connection = QueueConnection {
+            expected_write: b"=3\tfoo\n".to_vec(),
+            reads: VecDeque::from(vec![Ok(b"$3\tbar\n".to_vec())]),
+        };
+        let metrics = Metrics::new();
+
+        let mut client = Client::new(Box::new(connection));
+        client.set_metrics(metrics.clone());
+
+        client.get("foo").unwrap();
+        // the pipeline's write doesn't match, so neither get is answered
+        client.mget(vec!["foo", "foo"]);
+
+        let snapshot = metrics.snapshot();
+        let get = snapshot.op(Op::Get);
+        assert_eq!((get.requests, get.error_count(), get.errors.get("io")), (3, 2, Some(&2)));
+        assert_eq!(snapshot.op(Op::Set).requests, 0);
+    }
+
+    #[test]
+    fn get_opt_distinguishes_missing_and_empty() {
+        let connection = QueueConnection {
+            expected_write: b"=3\tfoo\n".to_vec(),
+            reads: VecDeque::from(vec![Ok(b"?\n".to_vec()), Ok(b"$\n".to_vec()), Ok(b"$3\tbar\n".to_vec())]),
+        };
+
+        let mut client = Client::new(Box::new(connection));
+
+        assert_eq!(client.get_opt("foo").unwrap(), None);
+        assert_eq!(client.get_opt("foo").unwrap(), Some(Vec::new()));
+        assert_eq!(client.get_opt("foo").unwrap(), Some(b"bar".to_vec()));
+    }
+
     #[test]
     fn set_success() {
         let key = b"foo";
@@ -193,7 +620,7 @@ mod tests {
 
         let mut client = Client::new(Box::new(connection));
 
-        let response = client.set(&key[..], &value[..]);
+        let response = client.set(&key[..], value);
 
         assert!(response.is_ok());
         assert_eq!(response.unwrap(), Response::Success);
@@ -211,7 +638,7 @@ mod tests {
 
         let mut client = Client::new(Box::new(connection));
 
-        let response = client.set(&key[..], &value[..]);
+        let response = client.set(&key[..], value);
 
         assert!(response.is_err());
 
@@ -258,4 +685,196 @@ mod tests {
 
         assert!(matches!(response.unwrap_err(), _expected));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn pipeline_success() {
+        let connection = QueueConnection {
+            expected_write: b"=3\tfoo\n+3\tfoo3\tbar\n-3\tfoo\n".to_vec(),
+            reads: vec![Ok(b"$\n".to_vec()), Ok(b"$\n".to_vec()), Ok(b"$3\tbar\n".to_vec())].into_iter().collect(),
+        };
+
+        let mut client = Client::new(Box::new(connection));
+        let responses: Vec<Response> = client.pipeline()
+            .get("foo")
+            .set("foo", "bar")
+            .delete("foo")
+            .execute()
+            .into_iter()
+            .map(|r| r.unwrap())
+            .collect();
+
+        assert_eq!(responses, vec![Response::Success, Response::Success, Response::SuccessWithResult(b"bar".to_vec())]);
+    }
+
+    #[test]
+    fn pipeline_empty() {
+        let connection = QueueConnection {
+            expected_write: Vec::new(),
+            reads: VecDeque::new(),
+        };
+
+        let mut client = Client::new(Box::new(connection));
+
+        assert!(client.pipeline().execute().is_empty());
+    }
+
+    #[test]
+    fn pipeline_aborts_after_connection_error() {
+        let connection = QueueConnection {
+            expected_write: b"=1\ta\n=1\tb\n=1\tc\n".to_vec(),
+            reads: vec![Ok(b"$\n".to_vec())].into_iter().collect(),
+        };
+
+        let mut client = Client::new(Box::new(connection));
+        let responses = client.pipeline().get("a").get("b").get("c").execute();
+
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0].as_ref().unwrap(), &Response::Success);
+        assert!(matches!(responses[1], Err(ref e) if matches!(e.kind(), ErrorKind::IO(e) if e.kind() == io::ErrorKind::UnexpectedEof)));
+        assert!(matches!(responses[2], Err(ref e) if matches!(e.kind(), ErrorKind::IO(e) if e.kind() == io::ErrorKind::ConnectionAborted)));
+    }
+
+    #[test]
+    fn mget_success() {
+        let connection = QueueConnection {
+            expected_write: b"=1\ta\n=1\tb\n".to_vec(),
+            reads: vec![Ok(b"$1\t1\n".to_vec()), Ok(b"$1\t2\n".to_vec())].into_iter().collect(),
+        };
+
+        let mut client = Client::new(Box::new(connection));
+        let responses: Vec<Response> = client.mget(vec!["a", "b"]).into_iter().map(|r| r.unwrap()).collect();
+
+        assert_eq!(responses, vec![Response::SuccessWithResult(b"1".to_vec()), Response::SuccessWithResult(b"2".to_vec())]);
+    }
+
+    #[test]
+    fn mset_success() {
+        let connection = QueueConnection {
+            expected_write: b"+1\ta1\t1\n+1\tb1\t2\n".to_vec(),
+            reads: vec![Ok(b"$\n".to_vec()), Ok(b"$\n".to_vec())].into_iter().collect(),
+        };
+
+        let mut client = Client::new(Box::new(connection));
+        let responses: Vec<Response> = client.mset(vec![("a", "1"), ("b", "2")]).into_iter().map(|r| r.unwrap()).collect();
+
+        assert_eq!(responses, vec![Response::Success, Response::Success]);
+    }
+
+    #[test]
+    fn mdel_success() {
+        let keys = vec![String::from("a"), String::from("b")];
+
+        let connection = QueueConnection {
+            expected_write: b"-1\ta\n-1\tb\n".to_vec(),
+            reads: vec![Ok(b"$\n".to_vec()), Ok(b"!3\tErr\n".to_vec())].into_iter().collect(),
+        };
+
+        let mut client = Client::new(Box::new(connection));
+        let responses: Vec<Response> = client.mdel(&keys).into_iter().map(|r| r.unwrap()).collect();
+
+        assert_eq!(responses, vec![Response::Success, Response::Error(b"Err".to_vec())]);
+    }
+
+    #[test]
+    fn authenticate_success() {
+        let connection = MockConnection {
+            expected_write: b"*7\thunter2\n",
+            expected_read: b"$\n",
+        };
+
+        let client = Client::with_password(Box::new(connection), "hunter2");
+
+        assert!(client.is_ok());
+    }
+
+    #[test]
+    fn authenticate_rejected() {
+        let connection = MockConnection {
+            expected_write: b"*7\thunter3\n",
+            expected_read: b"!14\tinvalid secret\n",
+        };
+
+        let mut client = Client::new(Box::new(connection));
+        let response = client.authenticate("hunter3");
+
+        assert!(matches!(response.unwrap_err().kind(), ErrorKind::Auth(msg) if msg == "invalid secret"));
+    }
+
+    fn failing_then(expected_write: &'static [u8], read: &'static [u8]) -> impl FnMut() -> Result<Box<dyn Connection>, TamariError> + Send {
+        // popped from the back, so the first connection handed out has no reads
+        let mut connections = vec![
+            QueueConnection { expected_write: expected_write.to_vec(), reads: vec![Ok(read.to_vec())].into_iter().collect() },
+            QueueConnection { expected_write: expected_write.to_vec(), reads: VecDeque::new() },
+        ];
+
+        move || match connections.pop() {
+            Some(connection) => Ok(Box::new(connection) as Box<dyn Connection>),
+            None => Err(TamariError::new(ErrorKind::IO(io::Error::new(io::ErrorKind::ConnectionRefused, "refused")))),
+        }
+    }
+
+    fn retrying_client() -> Client {
+        let connection = QueueConnection { expected_write: Vec::new(), reads: VecDeque::new() };
+
+        let mut client = Client::new(Box::new(connection));
+        client.set_retry_policy(RetryPolicy::new(3).base_delay(std::time::Duration::from_millis(1)));
+        client
+    }
+
+    #[test]
+    fn get_retries_after_reconnect() {
+        let mut client = retrying_client();
+        let mut factory = failing_then(b"=3\tfoo\n", b"$3\tbar\n");
+        client.connection = factory().unwrap();
+        client.set_reconnect(factory);
+
+        assert_eq!(client.get("foo").unwrap(), Response::SuccessWithResult(b"bar".to_vec()));
+    }
+
+    #[test]
+    fn set_not_retried_by_default() {
+        let mut client = retrying_client();
+        let mut factory = failing_then(b"+3\tfoo3\tbar\n", b"$\n");
+        client.connection = factory().unwrap();
+        client.set_reconnect(factory);
+
+        assert!(client.set("foo", "bar").is_err());
+    }
+
+    #[test]
+    fn set_retried_when_opted_in() {
+        let mut client = retrying_client();
+        client.set_retry_policy(RetryPolicy::new(3).base_delay(std::time::Duration::from_millis(1)).retry_sets(true));
+        let mut factory = failing_then(b"+3\tfoo3\tbar\n", b"$\n");
+        client.connection = factory().unwrap();
+        client.set_reconnect(factory);
+
+        assert_eq!(client.set("foo", "bar").unwrap(), Response::Success);
+    }
+
+    #[test]
+    fn retries_give_up_after_max_attempts() {
+        let mut client = retrying_client();
+        client.set_reconnect(|| Err(TamariError::new(ErrorKind::IO(io::Error::new(io::ErrorKind::ConnectionRefused, "refused")))));
+
+        let response = client.get("foo");
+
+        assert!(matches!(response.unwrap_err().kind(), ErrorKind::IO(e) if e.kind() == io::ErrorKind::ConnectionRefused));
+    }
+
+    #[test]
+    fn reconnect_authenticates_again() {
+        let connection = MockConnection {
+            expected_write: b"*7\thunter2\n",
+            expected_read: b"$\n",
+        };
+
+        let mut client = Client::with_password(Box::new(connection), "hunter2").unwrap();
+        client.set_reconnect(|| Ok(Box::new(MockConnection {
+            expected_write: b"*7\thunter2\n",
+            expected_read: b"!5\tnope!\n",
+        }) as Box<dyn Connection>));
+
+        assert!(matches!(client.reconnect().unwrap_err().kind(), ErrorKind::Auth(_)));
+    }
+}
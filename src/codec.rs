@@ -0,0 +1,81 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::error::{ErrorKind, TamariError};
+
+/// Turns structured values into stored bytes and back, for
+/// [`Client::set_serialized`](crate::Client::set_serialized) and
+/// [`Client::get_deserialized`](crate::Client::get_deserialized).
+pub trait Codec {
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, TamariError>;
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, TamariError>;
+}
+
+/// Stores values as JSON text, readable by other clients and from the CLI.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+impl Codec for Json {
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, TamariError> {
+        serde_json::to_vec(value).map_err(serialization_error)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, TamariError> {
+        serde_json::from_slice(bytes).map_err(serialization_error)
+    }
+}
+
+/// Stores values in bincode's compact binary format.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bincode;
+
+impl Codec for Bincode {
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, TamariError> {
+        bincode::serialize(value).map_err(serialization_error)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, TamariError> {
+        bincode::deserialize(bytes).map_err(serialization_error)
+    }
+}
+
+fn serialization_error<E: std::error::Error + Send + Sync + 'static>(err: E) -> TamariError {
+    TamariError::new(ErrorKind::Serialization(Box::new(err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct User {
+        name: String,
+        age: u32,
+    }
+
+    fn user() -> User {
+        User { name: String::from("sam"), age: 31 }
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let bytes = Json.encode(&user()).unwrap();
+
+        assert_eq!(bytes, br#"{"name":"sam","age":31}"#.to_vec());
+        assert_eq!(Json.decode::<User>(&bytes).unwrap(), user());
+    }
+
+    #[test]
+    fn bincode_round_trip() {
+        let bytes = Bincode.encode(&user()).unwrap();
+
+        assert_eq!(Bincode.decode::<User>(&bytes).unwrap(), user());
+    }
+
+    #[test]
+    fn decode_failure() {
+        let result = Json.decode::<User>(b"{\"name\":\"sam\"}");
+
+        assert!(matches!(result.unwrap_err().kind(), ErrorKind::Serialization(_)));
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Settings that may appear in a config file.
+const KEYS: &[&str] = &[
+    "address", "port", "socket", "password", "timeout", "connect_timeout", "format", "tls", "cacert", "cert", "key",
+];
+
+/// An INI-style config file.
+///
+/// Settings before the first `[section]` header apply to every profile. Each
+/// section is a named profile whose settings override the top-level ones.
+///
+/// ```text
+/// # defaults
+/// address = 127.0.0.1
+/// port = 8080
+///
+/// [staging]
+/// address = staging.internal
+/// password = "hunter2"
+/// ```
+#[derive(Debug, Default)]
+pub struct Config {
+    defaults: HashMap<String, String>,
+    profiles: HashMap<String, HashMap<String, String>>,
+}
+
+impl Config {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Config::parse(&contents),
+            Err(e) => Err(ConfigError::IO(e)),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        let mut profile: Option<String> = None;
+
+        for (i, line) in contents.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                match line.strip_prefix('[').and_then(|l| l.strip_suffix(']')).map(str::trim) {
+                    Some(name) if !name.is_empty() => {
+                        config.profiles.entry(String::from(name)).or_default();
+                        profile = Some(String::from(name));
+                    },
+                    _ => return Err(ConfigError::Syntax(line_number, String::from("invalid section header"))),
+                }
+
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), unquote(value.trim())),
+                None => return Err(ConfigError::Syntax(line_number, String::from("expected \"key = value\""))),
+            };
+
+            if !KEYS.contains(&key) {
+                return Err(ConfigError::Syntax(line_number, format!("unknown setting \"{}\"", key)));
+            }
+
+            let settings = match profile {
+                Some(ref name) => config.profiles.entry(name.clone()).or_default(),
+                None => &mut config.defaults,
+            };
+
+            settings.insert(String::from(key), String::from(value));
+        }
+
+        Ok(config)
+    }
+
+    pub fn has_profile(&self, name: &str) -> bool {
+        self.profiles.contains_key(name)
+    }
+
+    /// Looks up a setting in `profile`, falling back to the top-level value.
+    pub fn get(&self, profile: Option<&str>, key: &str) -> Option<&str> {
+        profile
+            .and_then(|name| self.profiles.get(name))
+            .and_then(|settings| settings.get(key))
+            .or_else(|| self.defaults.get(key))
+            .map(String::as_str)
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    if 2 <= value.len() && ((value.starts_with('"') && value.ends_with('"')) || (value.starts_with('\'') && value.ends_with('\''))) {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    IO(io::Error),
+    Syntax(usize, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::IO(ref err) => write!(f, "IO error: {}", err),
+            ConfigError::Syntax(line, ref msg) => write!(f, "line {}: {}", line, msg),
+        }
+    }
+}
+
+impl error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ConfigError::IO(ref err) => Some(err),
+            ConfigError::Syntax(_, _) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "
+        # defaults
+        address = 127.0.0.1
+        port = 8080
+
+        [staging]
+        address = staging.internal
+        password = \"hunter2\"
+
+        [empty]
+    ";
+
+    #[test]
+    fn get_default_setting() {
+        let config = Config::parse(CONFIG).unwrap();
+
+        assert_eq!(config.get(None, "address"), Some("127.0.0.1"));
+        assert_eq!(config.get(None, "password"), None);
+    }
+
+    #[test]
+    fn get_profile_setting() {
+        let config = Config::parse(CONFIG).unwrap();
+
+        assert_eq!(config.get(Some("staging"), "address"), Some("staging.internal"));
+        assert_eq!(config.get(Some("staging"), "password"), Some("hunter2"));
+        assert_eq!(config.get(Some("staging"), "port"), Some("8080"));
+        assert!(config.has_profile("empty"));
+        assert!(!config.has_profile("prod"));
+    }
+
+    #[test]
+    fn parse_invalid_config() {
+        assert!(matches!(Config::parse("address"), Err(ConfigError::Syntax(1, _))));
+        assert!(matches!(Config::parse("\n[staging"), Err(ConfigError::Syntax(2, _))));
+        assert!(matches!(Config::parse("colour = red"), Err(ConfigError::Syntax(1, _))));
+    }
+}
@@ -1,44 +1,497 @@
-use std::net;
+use std::net::{self, ToSocketAddrs};
+use std::time::Duration;
 use crate::error::{TamariError, ErrorKind};
-use std::io::{Write, BufRead, BufReader, BufWriter};
+use crate::parser::{Decoded, ResponseDecoder};
+use crate::connection_info::{ConnectionAddr, ConnectionInfo};
+use std::io::{self, Read, Write};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::Path;
+#[cfg(feature = "tls")]
+use crate::tls::TlsConnection;
 
-use std::str;
+pub(crate) const READ_CHUNK_SIZE: usize = 4096;
 
-pub trait Connection: {
+pub trait Connection: Send {
     fn read(&mut self) -> Result<Vec<u8>, TamariError>;
     fn write(&mut self, buffer: &[u8]) -> Result<(), TamariError>;
 }
 
 pub struct TcpConnection {
     stream: net::TcpStream,
+    frames: FrameBuffer,
 }
 
 impl Connection for TcpConnection {
+    /// Reads exactly one complete response frame, issuing as many reads on the
+    /// stream as it takes. Bytes received past the end of the frame are kept
+    /// for the next call.
     fn read(&mut self) -> Result<Vec<u8>, TamariError> {
-        
-        let mut reader = BufReader::new(&self.stream);
-        let mut response = String::new();
+        self.frames.read_frame(&mut self.stream)
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> Result<(), TamariError> {
+        self.frames.write_frames(&mut self.stream, buffer)
+    }
+}
+
+/// Response framing shared by the stream based connections, blocking or not,
+/// which feed it the bytes they read.
+///
+/// Frames are returned raw, for the client to parse, and a complete frame with
+/// invalid content is still returned whole for `parse_response` to reject, so
+/// that the frames after it stay readable.
+///
+/// Once a read or write has timed out, the late reply may still arrive and
+/// would be taken for the answer to the next request, so every later call
+/// fails with an `ErrorKind::IO` error instead and the connection has to be
+/// replaced.
+pub(crate) struct FrameBuffer {
+    // bytes received from the server that are not yet part of a returned frame
+    buffer: Vec<u8>,
+    decoder: ResponseDecoder,
+    broken: bool,
+}
+
+impl FrameBuffer {
+    pub(crate) fn new() -> Self {
+        FrameBuffer { buffer: Vec::new(), decoder: ResponseDecoder::new(), broken: false }
+    }
 
-        match reader.read_line(&mut response) {
-            Ok(_) => return Ok(response.as_bytes().to_vec()), // maybe trim here too
-            Err(e) => return Err(TamariError::new(ErrorKind::IO(e)))
+    /// Splits off the next frame if it has arrived completely.
+    pub(crate) fn next_frame(&mut self) -> Result<Option<Vec<u8>>, TamariError> {
+        match self.decoder.decode(&self.buffer) {
+            Ok(Decoded::Complete(_, len)) | Ok(Decoded::Invalid(_, len)) => Ok(Some(self.buffer.drain(..len).collect())),
+            Ok(Decoded::Incomplete(_)) => Ok(None),
+            Err(e) => {
+                // without a valid frame boundary the stream can't be
+                // resynchronised, so drop what we have
+                self.buffer.clear();
+                Err(TamariError::new(ErrorKind::Parse(e)))
+            },
         }
     }
 
-    fn write(&mut self, buffer: &[u8]) -> Result<(), TamariError> {
-        let mut writer = BufWriter::new(&self.stream);
-        match writer.write_all(buffer) {
-            Ok(_) => return Ok(()),
-            Err(e) => return Err(TamariError::new(ErrorKind::IO(e))),
+    /// Takes in the outcome of reading into `chunk` from the stream.
+    pub(crate) fn fill(&mut self, read: io::Result<usize>, chunk: &[u8]) -> Result<(), TamariError> {
+        match read {
+            Ok(0) => Err(TamariError::new(ErrorKind::IO(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed before a complete response was received",
+            )))),
+            Ok(n) => {
+                self.buffer.extend_from_slice(&chunk[..n]);
+                Ok(())
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => Ok(()),
+            Err(e) => Err(TamariError::from(e)),
         }
     }
+
+    /// Fails if the connection is broken. Called before every read and write.
+    pub(crate) fn check(&self) -> Result<(), TamariError> {
+        if self.broken {
+            return Err(TamariError::new(ErrorKind::IO(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "connection is unusable after a timeout",
+            ))));
+        }
+
+        Ok(())
+    }
+
+    /// Marks the connection broken if a read or write timed out.
+    pub(crate) fn track<T>(&mut self, result: Result<T, TamariError>) -> Result<T, TamariError> {
+        self.broken = matches!(result, Err(ref e) if matches!(e.kind(), ErrorKind::Timeout(_)));
+        result
+    }
+
+    pub(crate) fn read_frame<R: Read>(&mut self, stream: &mut R) -> Result<Vec<u8>, TamariError> {
+        self.check()?;
+
+        let mut chunk = [0; READ_CHUNK_SIZE];
+        let result = loop {
+            match self.next_frame() {
+                Ok(Some(frame)) => break Ok(frame),
+                Ok(None) => (),
+                Err(e) => break Err(e),
+            }
+
+            let read = stream.read(&mut chunk);
+            if let Err(e) = self.fill(read, &chunk) {
+                break Err(e);
+            }
+        };
+
+        self.track(result)
+    }
+
+    /// Writes requests to `stream` on behalf of the connection, so that they
+    /// are refused once it is broken too.
+    pub(crate) fn write_frames<W: Write>(&mut self, stream: &mut W, buffer: &[u8]) -> Result<(), TamariError> {
+        self.check()?;
+
+        let result = stream.write_all(buffer).and_then(|_| stream.flush()).map_err(TamariError::from);
+
+        self.track(result)
+    }
 }
 
 impl TcpConnection {
     pub fn new(addr: &str, port: u16) -> Result<Self, TamariError> {
         match net::TcpStream::connect((addr, port)) {
-            Ok(stream) => return Ok(TcpConnection { stream: stream }),
-            Err(e) => return Err(TamariError::new(ErrorKind::IO(e))),
+            Ok(stream) => Ok(TcpConnection::from_stream(stream)),
+            Err(e) => Err(TamariError::from(e)),
+        }
+    }
+
+    pub fn builder(addr: &str, port: u16) -> TcpConnectionBuilder {
+        TcpConnectionBuilder::new(addr, port)
+    }
+
+    /// Connects to the server described by a `tamari://` URL. Any password in
+    /// the URL is ignored here; use `Client::from_url` to authenticate as well.
+    pub fn from_url(url: &str) -> Result<Self, TamariError> {
+        TcpConnection::from_info(&ConnectionInfo::from_url(url)?)
+    }
+
+    pub fn from_info(info: &ConnectionInfo) -> Result<Self, TamariError> {
+        let mut builder = match info.addr {
+            ConnectionAddr::Tcp(_, _) if info.tls.is_some() => {
+                return Err(TamariError::new(ErrorKind::InvalidUrl(String::from("not a plain TCP address"))));
+            },
+            ConnectionAddr::Tcp(ref addr, port) => TcpConnection::builder(addr, port),
+            ConnectionAddr::Unix(_) => {
+                return Err(TamariError::new(ErrorKind::InvalidUrl(String::from("not a TCP address"))));
+            },
         };
+
+        if let Some(timeout) = info.timeout {
+            builder = builder.read_timeout(timeout).write_timeout(timeout);
+        }
+
+        if let Some(timeout) = info.connect_timeout.or(info.timeout) {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        builder.connect()
+    }
+
+    fn from_stream(stream: net::TcpStream) -> Self {
+        TcpConnection { stream, frames: FrameBuffer::new() }
+    }
+}
+
+/// Configures the socket options of a [`TcpConnection`] before connecting.
+///
+/// By default there are no timeouts, `TCP_NODELAY` is enabled since requests
+/// are small and latency bound, and keepalive is left to the OS.
+#[derive(Debug, Clone)]
+pub struct TcpConnectionBuilder {
+    addr: String,
+    port: u16,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    nodelay: bool,
+    keepalive: Option<Duration>,
+}
+
+impl TcpConnectionBuilder {
+    pub fn new(addr: &str, port: u16) -> Self {
+        TcpConnectionBuilder {
+            addr: String::from(addr),
+            port,
+            connect_timeout: None,
+            read_timeout: None,
+            write_timeout: None,
+            nodelay: true,
+            keepalive: None,
+        }
+    }
+
+    /// Bounds the time spent establishing the connection, per resolved address.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Bounds the time a single read from the server may block.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Bounds the time a single write to the server may block.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
     }
-}
\ No newline at end of file
+
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Enables TCP keepalive, sending the first probe after the connection has
+    /// been idle for `idle`.
+    pub fn keepalive(mut self, idle: Duration) -> Self {
+        self.keepalive = Some(idle);
+        self
+    }
+
+    pub fn connect(self) -> Result<TcpConnection, TamariError> {
+        Ok(TcpConnection::from_stream(self.connect_stream()?))
+    }
+
+    /// Connects and applies the socket options, for connections layered on top
+    /// of TCP.
+    pub(crate) fn connect_stream(&self) -> Result<net::TcpStream, TamariError> {
+        let stream = match self.connect_timeout {
+            Some(timeout) => connect_timeout(&self.addr, self.port, timeout)?,
+            None => net::TcpStream::connect((&self.addr[..], self.port))?,
+        };
+
+        stream.set_read_timeout(self.read_timeout)?;
+        stream.set_write_timeout(self.write_timeout)?;
+        stream.set_nodelay(self.nodelay)?;
+
+        if let Some(idle) = self.keepalive {
+            let keepalive = socket2::TcpKeepalive::new().with_time(idle);
+            socket2::SockRef::from(&stream).set_tcp_keepalive(&keepalive)?;
+        }
+
+        Ok(stream)
+    }
+}
+
+// Tries each address the host resolves to in turn, like `TcpStream::connect`
+// does, returning the last error if none of them accept.
+fn connect_timeout(addr: &str, port: u16, timeout: Duration) -> Result<net::TcpStream, TamariError> {
+    let mut last_err = io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any address");
+
+    for socket_addr in (addr, port).to_socket_addrs()? {
+        match net::TcpStream::connect_timeout(&socket_addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = e,
+        }
+    }
+
+    Err(TamariError::from(last_err))
+}
+
+/// A connection over a Unix domain socket, for servers on the same host.
+#[cfg(unix)]
+pub struct UnixConnection {
+    stream: UnixStream,
+    frames: FrameBuffer,
+}
+
+#[cfg(unix)]
+impl Connection for UnixConnection {
+    fn read(&mut self) -> Result<Vec<u8>, TamariError> {
+        self.frames.read_frame(&mut self.stream)
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> Result<(), TamariError> {
+        self.frames.write_frames(&mut self.stream, buffer)
+    }
+}
+
+#[cfg(unix)]
+impl UnixConnection {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, TamariError> {
+        match UnixStream::connect(path) {
+            Ok(stream) => Ok(UnixConnection { stream, frames: FrameBuffer::new() }),
+            Err(e) => Err(TamariError::from(e)),
+        }
+    }
+
+    /// Connects to the server described by a `tamari+unix://` URL. Any password
+    /// in the URL is ignored here; use `Client::from_url` to authenticate as well.
+    pub fn from_url(url: &str) -> Result<Self, TamariError> {
+        UnixConnection::from_info(&ConnectionInfo::from_url(url)?)
+    }
+
+    /// Connecting to a local socket doesn't block, so only the read and write
+    /// timeouts of `info` are applied.
+    pub fn from_info(info: &ConnectionInfo) -> Result<Self, TamariError> {
+        let connection = match info.addr {
+            ConnectionAddr::Unix(ref path) => UnixConnection::new(path)?,
+            ConnectionAddr::Tcp(_, _) => {
+                return Err(TamariError::new(ErrorKind::InvalidUrl(String::from("not a Unix socket address"))));
+            },
+        };
+
+        connection.set_read_timeout(info.timeout)?;
+        connection.set_write_timeout(info.timeout)?;
+
+        Ok(connection)
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), TamariError> {
+        self.stream.set_read_timeout(timeout).map_err(TamariError::from)
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<(), TamariError> {
+        self.stream.set_write_timeout(timeout).map_err(TamariError::from)
+    }
+}
+
+/// Opens a connection of whichever kind `info` describes.
+pub fn connect(info: &ConnectionInfo) -> Result<Box<dyn Connection>, TamariError> {
+    match info.addr {
+        #[cfg(feature = "tls")]
+        ConnectionAddr::Tcp(_, _) if info.tls.is_some() => Ok(Box::new(TlsConnection::from_info(info)?)),
+        #[cfg(not(feature = "tls"))]
+        ConnectionAddr::Tcp(_, _) if info.tls.is_some() => {
+            Err(TamariError::new(ErrorKind::Tls(String::from("built without TLS support, enable the \"tls\" feature"))))
+        },
+        ConnectionAddr::Tcp(_, _) => Ok(Box::new(TcpConnection::from_info(info)?)),
+        ConnectionAddr::Unix(_) if info.tls.is_some() => {
+            Err(TamariError::new(ErrorKind::Tls(String::from("TLS is not supported over Unix sockets"))))
+        },
+        #[cfg(unix)]
+        ConnectionAddr::Unix(_) => Ok(Box::new(UnixConnection::from_info(info)?)),
+        #[cfg(not(unix))]
+        ConnectionAddr::Unix(_) => {
+            Err(TamariError::new(ErrorKind::InvalidUrl(String::from("Unix sockets are not supported on this platform"))))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    fn serve(chunks: Vec<&'static [u8]>) -> (net::SocketAddr, thread::JoinHandle<()>) {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            for chunk in chunks {
+                stream.write_all(chunk).unwrap();
+                stream.flush().unwrap();
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+
+        (addr, handle)
+    }
+
+    #[test]
+    fn read_frame_split_across_reads() {
+        let (addr, handle) = serve(vec![b"$6\tfo", b"o\nba", b"\n"]);
+        let mut connection = TcpConnection::new(&addr.ip().to_string(), addr.port()).unwrap();
+
+        assert_eq!(connection.read().unwrap(), b"$6\tfoo\nba\n".to_vec());
+
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn read_keeps_bytes_past_frame() {
+        let (addr, handle) = serve(vec![b"$3\tfoo\n$\n!3\tb"]);
+        let mut connection = TcpConnection::new(&addr.ip().to_string(), addr.port()).unwrap();
+
+        assert_eq!(connection.read().unwrap(), b"$3\tfoo\n".to_vec());
+        assert_eq!(connection.read().unwrap(), b"$\n".to_vec());
+
+        handle.join().unwrap();
+
+        let response = connection.read();
+        assert!(matches!(response.unwrap_err().kind(), ErrorKind::IO(e) if e.kind() == io::ErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn read_keeps_frames_after_invalid_content() {
+        let (addr, handle) = serve(vec![b"!\n?3\tfoo\n$1\tb\n"]);
+        let mut connection = TcpConnection::new(&addr.ip().to_string(), addr.port()).unwrap();
+
+        assert_eq!(connection.read().unwrap(), b"!\n".to_vec());
+        assert_eq!(connection.read().unwrap(), b"?3\tfoo\n".to_vec());
+        assert_eq!(connection.read().unwrap(), b"$1\tb\n".to_vec());
+
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn read_timeout() {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut connection = TcpConnection::builder(&addr.ip().to_string(), addr.port())
+            .connect_timeout(Duration::from_secs(1))
+            .read_timeout(Duration::from_millis(50))
+            .keepalive(Duration::from_secs(30))
+            .connect()
+            .unwrap();
+
+        let (_stream, _) = listener.accept().unwrap();
+
+        let response = connection.read();
+        assert!(matches!(response.unwrap_err().kind(), ErrorKind::Timeout(_)));
+    }
+
+    #[test]
+    fn unusable_after_timeout() {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 7];
+            stream.read_exact(&mut request).unwrap();
+            thread::sleep(Duration::from_millis(150));
+            stream.write_all(b"$5\tfirst\n").unwrap();
+        });
+
+        let mut connection = TcpConnection::builder(&addr.ip().to_string(), addr.port())
+            .read_timeout(Duration::from_millis(50))
+            .connect()
+            .unwrap();
+
+        connection.write(b"=3\taaa\n").unwrap();
+        assert!(matches!(connection.read().unwrap_err().kind(), ErrorKind::Timeout(_)));
+        handle.join().unwrap();
+
+        // the reply to the first request has arrived by now, and must not be
+        // taken for the reply to the second
+        assert!(matches!(connection.write(b"=3\tbbb\n").unwrap_err().kind(), ErrorKind::IO(_)));
+        assert!(matches!(connection.read().unwrap_err().kind(), ErrorKind::IO(e) if e.kind() == io::ErrorKind::NotConnected));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_read_frame_split_across_reads() {
+        use std::os::unix::net::UnixListener;
+
+        let path = std::env::temp_dir().join(format!("tamari-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 7];
+            stream.read_exact(&mut request).unwrap();
+            assert_eq!(&request, b"=3\tfoo\n");
+
+            for chunk in [&b"$6\tfo"[..], b"o\nba", b"\n"].iter() {
+                stream.write_all(chunk).unwrap();
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+
+        let mut connection = UnixConnection::from_url(&format!("tamari+unix://{}", path.display())).unwrap();
+        connection.write(b"=3\tfoo\n").unwrap();
+
+        assert_eq!(connection.read().unwrap(), b"$6\tfoo\nba\n".to_vec());
+
+        handle.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,222 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use url::Url;
+use percent_encoding::percent_decode_str;
+use crate::error::{ErrorKind, TamariError};
+
+const TCP_SCHEME: &str = "tamari";
+const TLS_SCHEME: &str = "tamaris";
+const UNIX_SCHEME: &str = "tamari+unix";
+const DEFAULT_PORT: u16 = 8080;
+
+/// Where a TamariDB server can be reached.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionAddr {
+    Tcp(String, u16),
+    Unix(PathBuf),
+}
+
+/// How to secure a TCP connection with TLS.
+///
+/// Without a CA file the server certificate is verified against the bundled
+/// Mozilla root certificates. The server name sent for SNI and checked against
+/// the certificate defaults to the host being connected to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TlsSettings {
+    pub ca_file: Option<PathBuf>,
+    pub cert_file: Option<PathBuf>,
+    pub key_file: Option<PathBuf>,
+    pub server_name: Option<String>,
+}
+
+/// Everything needed to open and authenticate a connection, as described by a
+/// connection URL.
+///
+/// Three URL forms are accepted:
+///
+/// ```text
+/// tamari://[:password@]host[:port]/[?timeout=secs][&connect_timeout=secs]
+/// tamaris://[:password@]host[:port]/[?cacert=path][&cert=path&key=path][&sni=name][&timeout=secs]...
+/// tamari+unix://[:password@]/path/to/socket[?timeout=secs][&connect_timeout=secs]
+/// ```
+///
+/// `timeout` bounds each read and write. `connect_timeout` bounds connecting
+/// and falls back to `timeout` when it isn't given. `tamaris://` connects over
+/// TLS and additionally accepts the fields of [`TlsSettings`] as parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionInfo {
+    pub addr: ConnectionAddr,
+    pub password: Option<String>,
+    pub timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    pub tls: Option<TlsSettings>,
+}
+
+impl ConnectionInfo {
+    pub fn new(addr: ConnectionAddr) -> Self {
+        ConnectionInfo { addr, password: None, timeout: None, connect_timeout: None, tls: None }
+    }
+
+    pub fn from_url(url: &str) -> Result<Self, TamariError> {
+        let url = match Url::parse(url) {
+            Ok(url) => url,
+            Err(e) => return Err(invalid_url(e.to_string())),
+        };
+
+        let addr = match url.scheme() {
+            TCP_SCHEME | TLS_SCHEME => match url.host_str() {
+                Some(host) if !host.is_empty() => {
+                    // IPv6 hosts come back bracketed, which the socket APIs don't accept
+                    let host = host.trim_start_matches('[').trim_end_matches(']');
+                    ConnectionAddr::Tcp(String::from(host), url.port().unwrap_or(DEFAULT_PORT))
+                },
+                _ => return Err(invalid_url(String::from("missing host"))),
+            },
+            UNIX_SCHEME => {
+                if url.host_str().is_some_and(|host| !host.is_empty()) {
+                    return Err(invalid_url(String::from("socket URLs must not have a host")));
+                }
+
+                match percent_decode_str(url.path()).decode_utf8() {
+                    Ok(path) if path != "/" && !path.is_empty() => ConnectionAddr::Unix(PathBuf::from(path.into_owned())),
+                    Ok(_) => return Err(invalid_url(String::from("missing socket path"))),
+                    Err(_) => return Err(invalid_url(String::from("socket path is not valid UTF-8"))),
+                }
+            },
+            scheme => return Err(invalid_url(format!("unsupported scheme \"{}\"", scheme))),
+        };
+
+        let password = match url.password() {
+            Some(pw) => match percent_decode_str(pw).decode_utf8() {
+                Ok(pw) => Some(pw.into_owned()),
+                Err(_) => return Err(invalid_url(String::from("password is not valid UTF-8"))),
+            },
+            None => None,
+        };
+
+        let tls = if url.scheme() == TLS_SCHEME { Some(TlsSettings::default()) } else { None };
+        let mut info = ConnectionInfo { addr, password, timeout: None, connect_timeout: None, tls };
+
+        for (key, value) in url.query_pairs() {
+            let timeout = match (&key[..], info.tls.as_mut()) {
+                ("timeout", _) => &mut info.timeout,
+                ("connect_timeout", _) => &mut info.connect_timeout,
+                ("cacert", Some(tls)) => {
+                    tls.ca_file = Some(PathBuf::from(&value[..]));
+                    continue;
+                },
+                ("cert", Some(tls)) => {
+                    tls.cert_file = Some(PathBuf::from(&value[..]));
+                    continue;
+                },
+                ("key", Some(tls)) => {
+                    tls.key_file = Some(PathBuf::from(&value[..]));
+                    continue;
+                },
+                ("sni", Some(tls)) => {
+                    tls.server_name = Some(value.into_owned());
+                    continue;
+                },
+                _ => return Err(invalid_url(format!("unknown parameter \"{}\"", key))),
+            };
+
+            match value.parse::<f64>().ok().and_then(|secs| Duration::try_from_secs_f64(secs).ok()) {
+                Some(secs) => *timeout = Some(secs),
+                None => return Err(invalid_url(format!("invalid {} \"{}\"", key, value))),
+            }
+        }
+
+        Ok(info)
+    }
+}
+
+impl FromStr for ConnectionInfo {
+    type Err = TamariError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ConnectionInfo::from_url(s)
+    }
+}
+
+fn invalid_url(msg: String) -> TamariError {
+    TamariError::new(ErrorKind::InvalidUrl(msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_tcp_url() {
+        let info = ConnectionInfo::from_url("tamari://db.internal:9000/").unwrap();
+
+        assert_eq!(info, ConnectionInfo::new(ConnectionAddr::Tcp(String::from("db.internal"), 9000)));
+    }
+
+    #[test]
+    fn parse_tcp_url_with_password_and_timeout() {
+        let info = ConnectionInfo::from_url("tamari://:hunter%202@127.0.0.1/?timeout=1.5").unwrap();
+
+        assert_eq!(info.addr, ConnectionAddr::Tcp(String::from("127.0.0.1"), DEFAULT_PORT));
+        assert_eq!(info.password, Some(String::from("hunter 2")));
+        assert_eq!(info.timeout, Some(Duration::from_millis(1500)));
+        assert_eq!(info.connect_timeout, None);
+    }
+
+    #[test]
+    fn parse_url_with_connect_timeout() {
+        let info = ConnectionInfo::from_url("tamari://127.0.0.1/?connect_timeout=3").unwrap();
+
+        assert_eq!(info.timeout, None);
+        assert_eq!(info.connect_timeout, Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn parse_ipv6_url() {
+        let info: ConnectionInfo = "tamari://[::1]:9000".parse().unwrap();
+
+        assert_eq!(info.addr, ConnectionAddr::Tcp(String::from("::1"), 9000));
+    }
+
+    #[test]
+    fn parse_unix_url() {
+        let info = ConnectionInfo::from_url("tamari+unix:///var/run/tamari.sock").unwrap();
+
+        assert_eq!(info, ConnectionInfo::new(ConnectionAddr::Unix(PathBuf::from("/var/run/tamari.sock"))));
+    }
+
+    #[test]
+    fn parse_tls_url() {
+        let info = ConnectionInfo::from_url("tamaris://db.internal/?cacert=%2Fetc%2Fca.pem&sni=tamari.internal&timeout=2").unwrap();
+
+        assert_eq!(info.addr, ConnectionAddr::Tcp(String::from("db.internal"), DEFAULT_PORT));
+        assert_eq!(info.timeout, Some(Duration::from_secs(2)));
+        assert_eq!(info.tls, Some(TlsSettings {
+            ca_file: Some(PathBuf::from("/etc/ca.pem")),
+            server_name: Some(String::from("tamari.internal")),
+            ..TlsSettings::default()
+        }));
+    }
+
+    #[test]
+    fn parse_invalid_urls() {
+        let urls = [
+            "db.internal:9000",
+            "http://db.internal",
+            "tamari://",
+            "tamari://db.internal:99999",
+            "tamari://db.internal/?timeout=soon",
+            "tamari://db.internal/?timeout=-1",
+            "tamari://db.internal/?retries=3",
+            "tamari://db.internal/?cacert=ca.pem",
+            "tamari+unix:///",
+            "tamari+unix://host/var/run/tamari.sock",
+        ];
+
+        for url in urls.iter() {
+            let result = ConnectionInfo::from_url(url);
+            assert!(matches!(result, Err(ref e) if matches!(e.kind(), ErrorKind::InvalidUrl(_))), "{}", url);
+        }
+    }
+}
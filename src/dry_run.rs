@@ -0,0 +1,231 @@
+use std::ascii;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use crate::connection::Connection;
+use crate::error::TamariError;
+use crate::parser::{self, Response};
+use crate::protocol::{self, Request};
+
+// the reply to requests that weren't given one, which every request accepts
+const DEFAULT_REPLY: &[u8] = b"$\n";
+
+const DUMP_WIDTH: usize = 16;
+
+/// A connection that sends nothing anywhere, for seeing what a client would
+/// send and how it handles the replies.
+///
+/// Every frame written is printed as a hex/ASCII dump with its decoded fields,
+/// and so is every reply read back. Replies are served from those given to
+/// [`DryRunConnection::reply`] in order, then as `$\n` once they run out.
+///
+/// ```text
+/// > request, 7 bytes
+///   00000000  3d 33 09 66 6f 6f 0a                              |=3.foo.|
+///   get (=): key, 3 bytes: "foo"
+/// < reply, 7 bytes
+///   00000000  24 33 09 62 61 72 0a                              |$3.bar.|
+///   success ($): value, 3 bytes: "bar"
+/// ```
+pub struct DryRunConnection {
+    replies: VecDeque<Vec<u8>>,
+    output: Box<dyn Write + Send>,
+}
+
+impl DryRunConnection {
+    /// Prints to stdout.
+    pub fn new() -> Self {
+        DryRunConnection::with_output(io::stdout())
+    }
+
+    pub fn with_output<W: Write + Send + 'static>(output: W) -> Self {
+        DryRunConnection { replies: VecDeque::new(), output: Box::new(output) }
+    }
+
+    /// Queues a raw response frame, such as `b"$3\tbar\n"`, to be read after
+    /// the ones already queued. Frames aren't checked, so invalid ones can be
+    /// used to see how parse errors are handled.
+    pub fn reply<T: Into<Vec<u8>>>(mut self, frame: T) -> Self {
+        self.replies.push_back(frame.into());
+        self
+    }
+}
+
+impl Default for DryRunConnection {
+    fn default() -> Self {
+        DryRunConnection::new()
+    }
+}
+
+impl Connection for DryRunConnection {
+    fn read(&mut self) -> Result<Vec<u8>, TamariError> {
+        let frame = self.replies.pop_front().unwrap_or_else(|| DEFAULT_REPLY.to_vec());
+
+        let annotation = describe_reply(&frame);
+        self.output.write_all(dump("< reply", &frame, &[annotation]).as_bytes())?;
+
+        Ok(frame)
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> Result<(), TamariError> {
+        for (frame, annotation) in describe_requests(buffer) {
+            self.output.write_all(dump("> request", frame, &[annotation]).as_bytes())?;
+        }
+
+        self.output.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Splits written bytes into their request frames, each with a description of
+/// its decoded fields. Pipelines write many frames at once.
+pub(crate) fn describe_requests(buffer: &[u8]) -> Vec<(&[u8], String)> {
+    let mut frames = Vec::new();
+    let mut rest = buffer;
+
+    while !rest.is_empty() {
+        let (len, description) = match protocol::decode_request(rest) {
+            Ok(Some((request, len))) => (len, describe_request(&request)),
+            Ok(None) => (rest.len(), String::from("incomplete request")),
+            Err(e) => (rest.len(), format!("invalid request: {}", e)),
+        };
+
+        frames.push((&rest[..len], description));
+        rest = &rest[len..];
+    }
+
+    frames
+}
+
+/// Describes the decoded fields of a response frame.
+pub(crate) fn describe_reply(frame: &[u8]) -> String {
+    match parser::parse_response(frame) {
+        Ok(response) => describe_response(&response),
+        Err(e) => format!("invalid reply: {}", e),
+    }
+}
+
+fn describe_request(request: &Request) -> String {
+    match request {
+        Request::Get(key) => format!("get (=): {}", field("key", key)),
+        Request::Set(key, value) => format!("set (+): {}; {}", field("key", key), field("value", value)),
+        Request::Delete(key) => format!("delete (-): {}", field("key", key)),
+        // passwords don't belong on screen
+        Request::Auth(password) => format!("auth (*): password, {} bytes", password.len()),
+    }
+}
+
+fn describe_response(response: &Response) -> String {
+    match response {
+        Response::Success => String::from("success ($)"),
+        Response::SuccessWithResult(value) => format!("success ($): {}", field("value", value)),
+        Response::Error(msg) => format!("error (!): {}", field("message", msg)),
+        Response::NotFound => String::from("not found (?)"),
+        Response::Multi(values) => {
+            let fields: Vec<String> = values.iter().enumerate()
+                .map(|(i, value)| field(&format!("value {}", i + 1), value))
+                .collect();
+            format!("success ($): {}", fields.join("; "))
+        },
+    }
+}
+
+fn field(name: &str, bytes: &[u8]) -> String {
+    let escaped: String = bytes.iter().flat_map(|b| ascii::escape_default(*b)).map(char::from).collect();
+    format!("{}, {} bytes: \"{}\"", name, bytes.len(), escaped)
+}
+
+// Formats `bytes` like `hexdump -C`, under a heading and above the annotations.
+fn dump(heading: &str, bytes: &[u8], annotations: &[String]) -> String {
+    let mut out = format!("{}, {} bytes\n", heading, bytes.len());
+
+    for (i, line) in bytes.chunks(DUMP_WIDTH).enumerate() {
+        let _ = write!(out, "  {:08x} ", i * DUMP_WIDTH);
+
+        for j in 0..DUMP_WIDTH {
+            if j % 8 == 0 {
+                out.push(' ');
+            }
+            match line.get(j) {
+                Some(b) => {
+                    let _ = write!(out, "{:02x} ", b);
+                },
+                None => out.push_str("   "),
+            }
+        }
+
+        let ascii: String = line.iter()
+            .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+            .collect();
+        let _ = writeln!(out, " |{}|", ascii);
+    }
+
+    for annotation in annotations {
+        let _ = writeln!(out, "  {}", annotation);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::testing::Output;
+
+    #[test]
+    fn dumps_requests_and_replies() {
+        let output = Output::default();
+        let connection = DryRunConnection::with_output(output.clone()).reply(&b"$3\tbar\n"[..]);
+        let mut client = Client::new(Box::new(connection));
+
+        assert_eq!(client.get("foo").unwrap(), Response::SuccessWithResult(b"bar".to_vec()));
+        assert_eq!(output.text(), concat!(
+            "> request, 7 bytes\n",
+            "  00000000  3d 33 09 66 6f 6f 0a                              |=3.foo.|\n",
+            "  get (=): key, 3 bytes: \"foo\"\n",
+            "< reply, 7 bytes\n",
+            "  00000000  24 33 09 62 61 72 0a                              |$3.bar.|\n",
+            "  success ($): value, 3 bytes: \"bar\"\n",
+        ));
+    }
+
+    #[test]
+    fn dumps_binary_pipelines() {
+        let output = Output::default();
+        let connection = DryRunConnection::with_output(output.clone()).reply(&b"?\n"[..]);
+        let mut client = Client::new(Box::new(connection));
+
+        let responses = client.pipeline().set("k", vec![0u8, 0xff, b'"', b'\t', b'a', b'b', b'c', b'd', b'e']).get("k").execute();
+
+        assert_eq!(responses.into_iter().map(Result::unwrap).collect::<Vec<_>>(), vec![Response::NotFound, Response::Success]);
+        assert_eq!(output.text(), concat!(
+            "> request, 16 bytes\n",
+            "  00000000  2b 31 09 6b 39 09 00 ff  22 09 61 62 63 64 65 0a  |+1.k9...\".abcde.|\n",
+            "  set (+): key, 1 bytes: \"k\"; value, 9 bytes: \"\\x00\\xff\\\"\\tabcde\"\n",
+            "> request, 5 bytes\n",
+            "  00000000  3d 31 09 6b 0a                                    |=1.k.|\n",
+            "  get (=): key, 1 bytes: \"k\"\n",
+            "< reply, 2 bytes\n",
+            "  00000000  3f 0a                                             |?.|\n",
+            "  not found (?)\n",
+            "< reply, 2 bytes\n",
+            "  00000000  24 0a                                             |$.|\n",
+            "  success ($)\n",
+        ));
+    }
+
+    #[test]
+    fn annotates_invalid_frames() {
+        let output = Output::default();
+        let mut connection = DryRunConnection::with_output(output.clone()).reply(&b"%oops\n"[..]);
+
+        connection.write(b"=x\n").unwrap();
+        connection.read().unwrap();
+
+        let text = output.text();
+        assert!(text.contains("  invalid request: "), "{}", text);
+        assert!(text.contains("  invalid reply: "), "{}", text);
+    }
+}
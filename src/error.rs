@@ -16,12 +16,35 @@ impl TamariError {
     pub fn new(e: ErrorKind) -> TamariError {
         TamariError { kind: e }
     }
+
+    /// Whether the server reported that the key doesn't exist.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self.kind, ErrorKind::Server(ref err) if err.is_not_found())
+    }
+
+    /// Whether the request failed because the client isn't, or couldn't be,
+    /// authenticated.
+    pub fn is_auth_error(&self) -> bool {
+        match self.kind {
+            ErrorKind::Auth(_) => true,
+            ErrorKind::Server(ref err) => err.is_auth_error(),
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum ErrorKind {
     IO(io::Error),
     Parse(ParseError),
+    Auth(String),
+    InvalidUrl(String),
+    Timeout(io::Error),
+    Tls(String),
+    Conversion(String),
+    Serialization(Box<dyn error::Error + Send + Sync>),
+    Server(ServerError),
+    Replay(String),
 }
 
 
@@ -30,6 +53,14 @@ impl fmt::Display for TamariError {
         match self.kind() {
             ErrorKind::IO(ref err) => write!(f, "IO error: {}", err),
             ErrorKind::Parse(ref msg) => write!(f, "Parse error: {}", msg),
+            ErrorKind::Auth(ref msg) => write!(f, "Authentication error: {}", msg),
+            ErrorKind::InvalidUrl(ref msg) => write!(f, "Invalid URL: {}", msg),
+            ErrorKind::Timeout(ref err) => write!(f, "Timed out: {}", err),
+            ErrorKind::Tls(ref msg) => write!(f, "TLS error: {}", msg),
+            ErrorKind::Conversion(ref msg) => write!(f, "Conversion error: {}", msg),
+            ErrorKind::Serialization(ref err) => write!(f, "Serialization error: {}", err),
+            ErrorKind::Server(ref err) => write!(f, "Server error: {}", err),
+            ErrorKind::Replay(ref msg) => write!(f, "Replay error: {}", msg),
         }
     }
 }
@@ -39,12 +70,108 @@ impl error::Error for TamariError {
         match self.kind() {
             ErrorKind::IO(ref err) => Some(err),
             ErrorKind::Parse(ref err) => Some(err),
+            ErrorKind::Auth(_) => None,
+            ErrorKind::InvalidUrl(_) => None,
+            ErrorKind::Timeout(ref err) => Some(err),
+            ErrorKind::Tls(_) => None,
+            ErrorKind::Conversion(_) => None,
+            ErrorKind::Serialization(ref err) => Some(err.as_ref()),
+            ErrorKind::Server(ref err) => Some(err),
+            ErrorKind::Replay(_) => None,
         }
     }
 }
 
+/// An error reported by the server in a `!` response.
+///
+/// The server starts its error messages with an upper case code, such as
+/// `NOTFOUND no such key`. Messages without one get the generic code `ERR`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerError {
+    code: String,
+    message: String,
+}
+
+impl ServerError {
+    pub fn new(code: &str, message: &str) -> Self {
+        ServerError { code: String::from(code), message: String::from(message) }
+    }
+
+    /// Parses the argument of a `!` response.
+    pub fn parse(raw: &[u8]) -> Self {
+        let text = String::from_utf8_lossy(raw);
+        let text = text.trim();
+        let (code, message) = text.split_once(' ').unwrap_or((text, ""));
+
+        if !code.is_empty() && code.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_') {
+            ServerError::new(code, message.trim_start())
+        } else {
+            ServerError::new("ERR", text)
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Whether the code is `NOTFOUND`.
+    pub fn is_not_found(&self) -> bool {
+        self.code == "NOTFOUND"
+    }
+
+    /// Whether the code is `NOAUTH`, for requests made before authenticating,
+    /// or `WRONGPASS`, for rejected passwords.
+    pub fn is_auth_error(&self) -> bool {
+        self.code == "NOAUTH" || self.code == "WRONGPASS"
+    }
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.message.is_empty() {
+            write!(f, "{}", self.code)
+        } else {
+            write!(f, "{} {}", self.code, self.message)
+        }
+    }
+}
+
+impl error::Error for ServerError {}
+
+/// Socket timeouts surface from blocking reads and writes as `TimedOut` or
+/// `WouldBlock` depending on the platform; both become `ErrorKind::Timeout`.
 impl From<io::Error> for TamariError {
     fn from(err: io::Error) -> Self {
-        TamariError { kind: ErrorKind::IO(err) }
+        match err.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => TamariError { kind: ErrorKind::Timeout(err) },
+            _ => TamariError { kind: ErrorKind::IO(err) },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_server_error() {
+        assert_eq!(ServerError::parse(b"NOTFOUND no such key"), ServerError::new("NOTFOUND", "no such key"));
+        assert_eq!(ServerError::parse(b"WRONGPASS"), ServerError::new("WRONGPASS", ""));
+        assert_eq!(ServerError::parse(b"something broke"), ServerError::new("ERR", "something broke"));
+        assert_eq!(ServerError::parse(b""), ServerError::new("ERR", ""));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn error_classes() {
+        let not_found = TamariError::new(ErrorKind::Server(ServerError::parse(b"NOTFOUND no such key")));
+        let no_auth = TamariError::new(ErrorKind::Server(ServerError::parse(b"NOAUTH authenticate first")));
+
+        assert!(not_found.is_not_found() && !not_found.is_auth_error());
+        assert!(no_auth.is_auth_error() && !no_auth.is_not_found());
+        assert!(TamariError::new(ErrorKind::Auth(String::from("rejected"))).is_auth_error());
+    }
+}
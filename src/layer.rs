@@ -0,0 +1,450 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::connection::Connection;
+use crate::dry_run;
+use crate::error::{ErrorKind, TamariError};
+use crate::protocol;
+
+/// Middleware that wraps a connection, to inspect or change what goes through
+/// it without changing the client.
+///
+/// Layers are added with [`Client::add_layer`](crate::Client::add_layer),
+/// which applies them again to every connection the client reconnects with.
+/// Closures taking and returning a boxed connection are layers too.
+pub trait Layer: Send + Sync {
+    fn layer(&self, inner: Box<dyn Connection>) -> Box<dyn Connection>;
+}
+
+impl<F> Layer for F
+where
+    F: Fn(Box<dyn Connection>) -> Box<dyn Connection> + Send + Sync,
+{
+    fn layer(&self, inner: Box<dyn Connection>) -> Box<dyn Connection> {
+        self(inner)
+    }
+}
+
+/// Logs every request and reply with its decoded fields, and every reply with
+/// the time since the request was written:
+///
+/// ```text
+/// > get (=): key, 3 bytes: "foo"
+/// < success ($): value, 3 bytes: "bar" (0.214ms)
+/// ```
+///
+/// Logging never fails a request: lines that can't be written are dropped.
+#[derive(Clone)]
+pub struct LoggingLayer {
+    output: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl LoggingLayer {
+    /// Logs to stderr.
+    pub fn new() -> Self {
+        LoggingLayer::with_output(io::stderr())
+    }
+
+    pub fn with_output<W: Write + Send + 'static>(output: W) -> Self {
+        LoggingLayer { output: Arc::new(Mutex::new(Box::new(output))) }
+    }
+}
+
+impl Default for LoggingLayer {
+    fn default() -> Self {
+        LoggingLayer::new()
+    }
+}
+
+impl Layer for LoggingLayer {
+    fn layer(&self, inner: Box<dyn Connection>) -> Box<dyn Connection> {
+        Box::new(LoggingConnection { inner, output: Arc::clone(&self.output), written: None })
+    }
+}
+
+struct LoggingConnection {
+    inner: Box<dyn Connection>,
+    output: Arc<Mutex<Box<dyn Write + Send>>>,
+    written: Option<Instant>,
+}
+
+impl LoggingConnection {
+    fn log(&self, lines: &[String]) {
+        // a panic elsewhere while logging can at worst have cut a line short
+        let mut output = self.output.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // the request went through either way, so errors are left out of it
+        let _ = lines.iter()
+            .try_for_each(|line| writeln!(output, "{}", line))
+            .and_then(|_| output.flush());
+    }
+}
+
+impl Connection for LoggingConnection {
+    fn read(&mut self) -> Result<Vec<u8>, TamariError> {
+        let result = self.inner.read();
+        let elapsed = self.written.map(|written| written.elapsed()).unwrap_or_default();
+
+        let line = match result {
+            Ok(ref frame) => format!("< {} ({:.3}ms)", dry_run::describe_reply(frame), elapsed.as_secs_f64() * 1000.0),
+            Err(ref e) => format!("< failed: {} ({:.3}ms)", e, elapsed.as_secs_f64() * 1000.0),
+        };
+        self.log(&[line]);
+
+        result
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> Result<(), TamariError> {
+        let lines: Vec<String> = dry_run::describe_requests(buffer).into_iter()
+            .map(|(_, description)| format!("> {}", description))
+            .collect();
+        self.log(&lines);
+
+        self.written = Some(Instant::now());
+        let result = self.inner.write(buffer);
+
+        if let Err(ref e) = result {
+            self.log(&[format!("> failed: {}", e)]);
+        }
+
+        result
+    }
+}
+
+/// Counts of what went through the connections of a [`MetricsLayer`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// Request frames written.
+    pub requests: u64,
+    /// Reply frames read.
+    pub replies: u64,
+    /// Failed reads and writes.
+    pub errors: u64,
+    pub bytes_written: u64,
+    pub bytes_read: u64,
+}
+
+/// Counts requests, replies, failures and bytes across every connection it
+/// wraps. Clones share their counts, so keep one to read them from.
+#[derive(Clone, Default)]
+pub struct MetricsLayer {
+    counters: Arc<Counters>,
+}
+
+#[derive(Default)]
+struct Counters {
+    requests: AtomicU64,
+    replies: AtomicU64,
+    errors: AtomicU64,
+    bytes_written: AtomicU64,
+    bytes_read: AtomicU64,
+}
+
+impl MetricsLayer {
+    pub fn new() -> Self {
+        MetricsLayer::default()
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        let counters = &self.counters;
+
+        ConnectionStats {
+            requests: counters.requests.load(Ordering::Relaxed),
+            replies: counters.replies.load(Ordering::Relaxed),
+            errors: counters.errors.load(Ordering::Relaxed),
+            bytes_written: counters.bytes_written.load(Ordering::Relaxed),
+            bytes_read: counters.bytes_read.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Layer for MetricsLayer {
+    fn layer(&self, inner: Box<dyn Connection>) -> Box<dyn Connection> {
+        Box::new(MetricsConnection { inner, counters: Arc::clone(&self.counters) })
+    }
+}
+
+struct MetricsConnection {
+    inner: Box<dyn Connection>,
+    counters: Arc<Counters>,
+}
+
+impl Connection for MetricsConnection {
+    fn read(&mut self) -> Result<Vec<u8>, TamariError> {
+        let result = self.inner.read();
+
+        match result {
+            Ok(ref frame) => {
+                self.counters.replies.fetch_add(1, Ordering::Relaxed);
+                self.counters.bytes_read.fetch_add(frame.len() as u64, Ordering::Relaxed);
+            },
+            Err(_) => {
+                self.counters.errors.fetch_add(1, Ordering::Relaxed);
+            },
+        }
+
+        result
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> Result<(), TamariError> {
+        let result = self.inner.write(buffer);
+
+        match result {
+            Ok(()) => {
+                self.counters.requests.fetch_add(count_requests(buffer), Ordering::Relaxed);
+                self.counters.bytes_written.fetch_add(buffer.len() as u64, Ordering::Relaxed);
+            },
+            Err(_) => {
+                self.counters.errors.fetch_add(1, Ordering::Relaxed);
+            },
+        }
+
+        result
+    }
+}
+
+// Counts the request frames in written bytes, with any trailing bytes that
+// aren't a valid frame as one more.
+fn count_requests(buffer: &[u8]) -> u64 {
+    let mut count = 0;
+    let mut rest = buffer;
+
+    while !rest.is_empty() {
+        count += 1;
+
+        match protocol::decode_request(rest) {
+            Ok(Some((_, len))) => rest = &rest[len..],
+            Ok(None) | Err(_) => break,
+        }
+    }
+
+    count
+}
+
+/// Fails reads and writes that take longer than a timeout with
+/// `ErrorKind::Timeout`, whatever kind of connection they are on.
+///
+/// The wrapped connection is driven from a thread of its own so that it can
+/// be given up on. Once it has timed out, it may still deliver the late reply,
+/// so the connection fails with `ErrorKind::IO` errors from then on and the
+/// client can reconnect.
+///
+/// A [`Connection`] can't be interrupted, so the thread and the wrapped
+/// connection are only freed once the call that timed out returns. A call
+/// that never returns leaks both; give the wrapped connection a timeout of its
+/// own, such as `TcpConnectionBuilder::read_timeout`, to bound how long they live.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeoutLayer {
+    timeout: Duration,
+}
+
+impl TimeoutLayer {
+    pub fn new(timeout: Duration) -> Self {
+        TimeoutLayer { timeout }
+    }
+}
+
+impl Layer for TimeoutLayer {
+    fn layer(&self, inner: Box<dyn Connection>) -> Box<dyn Connection> {
+        Box::new(TimeoutConnection { timeout: self.timeout, worker: Some(Worker::spawn(inner)) })
+    }
+}
+
+struct TimeoutConnection {
+    timeout: Duration,
+    // gone once the connection timed out
+    worker: Option<Worker>,
+}
+
+enum Command {
+    Read,
+    Write(Vec<u8>),
+}
+
+struct Worker {
+    commands: mpsc::Sender<Command>,
+    results: mpsc::Receiver<Result<Vec<u8>, TamariError>>,
+}
+
+impl Worker {
+    fn spawn(mut connection: Box<dyn Connection>) -> Worker {
+        let (commands, received) = mpsc::channel();
+        let (sender, results) = mpsc::channel();
+
+        // ends when the TimeoutConnection is dropped or gives up on it, but
+        // only after the call in progress, if any, returns
+        thread::spawn(move || {
+            for command in received {
+                let result = match command {
+                    Command::Read => connection.read(),
+                    Command::Write(buffer) => connection.write(&buffer).map(|_| Vec::new()),
+                };
+
+                if sender.send(result).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Worker { commands, results }
+    }
+}
+
+impl TimeoutConnection {
+    fn run(&mut self, command: Command) -> Result<Vec<u8>, TamariError> {
+        let worker = match self.worker {
+            Some(ref worker) => worker,
+            None => return Err(TamariError::new(ErrorKind::IO(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "connection was abandoned after a timeout",
+            )))),
+        };
+
+        let result = match worker.commands.send(command) {
+            Ok(()) => worker.results.recv_timeout(self.timeout),
+            Err(_) => Err(RecvTimeoutError::Disconnected),
+        };
+
+        match result {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => {
+                self.worker = None;
+                Err(TamariError::new(ErrorKind::Timeout(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("no reply within {:?}", self.timeout),
+                ))))
+            },
+            Err(RecvTimeoutError::Disconnected) => {
+                self.worker = None;
+                Err(TamariError::new(ErrorKind::IO(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "connection thread stopped",
+                ))))
+            },
+        }
+    }
+}
+
+impl Connection for TimeoutConnection {
+    fn read(&mut self) -> Result<Vec<u8>, TamariError> {
+        self.run(Command::Read)
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> Result<(), TamariError> {
+        self.run(Command::Write(buffer.to_vec())).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{Client, Response};
+    use crate::retry::RetryPolicy;
+    use crate::testing::{FailingOutput, FakeServer, Fault, Output};
+    use crate::TcpConnection;
+
+    // answers every request with `$\n`, after a delay
+    struct SlowConnection {
+        delay: Duration,
+    }
+
+    impl Connection for SlowConnection {
+        fn read(&mut self) -> Result<Vec<u8>, TamariError> {
+            thread::sleep(self.delay);
+            Ok(b"$\n".to_vec())
+        }
+
+        fn write(&mut self, _buffer: &[u8]) -> Result<(), TamariError> {
+            Ok(())
+        }
+    }
+
+    fn client(server: &FakeServer) -> Client {
+        let info = server.info().clone();
+        let mut client = Client::new(Box::new(TcpConnection::from_info(&info).unwrap()));
+        client.set_reconnect(move || Ok(Box::new(TcpConnection::from_info(&info)?)));
+        client
+    }
+
+    #[test]
+    fn logs_requests_and_replies() {
+        let server = FakeServer::tcp().unwrap();
+        let output = Output::default();
+        let mut client = client(&server);
+        client.add_layer(LoggingLayer::with_output(output.clone()));
+
+        client.set("foo", "bar").unwrap();
+        client.mget(vec!["foo", "baz"]);
+
+        let log = output.text();
+        // replies end in their timing, which varies
+        let lines: Vec<&str> = log.lines().map(|line| if line.starts_with('<') { line.rsplit_once(" (").unwrap().0 } else { line }).collect();
+        assert_eq!(lines, vec![
+            "> set (+): key, 3 bytes: \"foo\"; value, 3 bytes: \"bar\"",
+            "< success ($)",
+            "> get (=): key, 3 bytes: \"foo\"",
+            "> get (=): key, 3 bytes: \"baz\"",
+            "< success ($): value, 3 bytes: \"bar\"",
+            "< not found (?)",
+        ]);
+    }
+
+    #[test]
+    fn logging_failures_keep_replies() {
+        let server = FakeServer::tcp().unwrap();
+        let mut client = client(&server);
+        client.add_layer(LoggingLayer::with_output(FailingOutput(0)));
+
+        assert_eq!(client.set("foo", "bar").unwrap(), Response::Success);
+        assert_eq!(client.get("foo").unwrap(), Response::SuccessWithResult(b"bar".to_vec()));
+    }
+
+    #[test]
+    fn layers_apply_to_reconnects() {
+        let server = FakeServer::tcp().unwrap();
+        server.inject(Fault::Drop);
+        let metrics = MetricsLayer::new();
+
+        let mut client = client(&server);
+        client.set_retry_policy(RetryPolicy::new(2).base_delay(Duration::from_millis(1)));
+        client.add_layer(metrics.clone());
+        // closures work as layers, here one that turns every get into a get of "foo"
+        client.add_layer(|inner: Box<dyn Connection>| -> Box<dyn Connection> { Box::new(Rewrite(inner)) });
+
+        server.insert("foo", "bar");
+        assert_eq!(client.get("baz").unwrap(), Response::SuccessWithResult(b"bar".to_vec()));
+        assert_eq!(metrics.stats(), ConnectionStats { requests: 2, replies: 1, errors: 1, bytes_written: 14, bytes_read: 7 });
+    }
+
+    struct Rewrite(Box<dyn Connection>);
+
+    impl Connection for Rewrite {
+        fn read(&mut self) -> Result<Vec<u8>, TamariError> {
+            self.0.read()
+        }
+
+        fn write(&mut self, buffer: &[u8]) -> Result<(), TamariError> {
+            match buffer {
+                b"=3\tbaz\n" => self.0.write(b"=3\tfoo\n"),
+                _ => self.0.write(buffer),
+            }
+        }
+    }
+
+    #[test]
+    fn times_out_slow_connections() {
+        let layer = TimeoutLayer::new(Duration::from_millis(20));
+
+        let mut connection = layer.layer(Box::new(SlowConnection { delay: Duration::from_millis(1) }));
+        connection.write(b"=3\tfoo\n").unwrap();
+        assert_eq!(connection.read().unwrap(), b"$\n".to_vec());
+
+        let mut connection = layer.layer(Box::new(SlowConnection { delay: Duration::from_millis(500) }));
+        connection.write(b"=3\tfoo\n").unwrap();
+        assert!(matches!(connection.read().unwrap_err().kind(), ErrorKind::Timeout(_)));
+        assert!(matches!(connection.read().unwrap_err().kind(), ErrorKind::IO(_)));
+    }
+}
@@ -2,7 +2,40 @@ mod error;
 mod client;
 mod parser;
 mod connection;
+mod connection_info;
+mod retry;
+mod pool;
+mod types;
+mod record;
+mod dry_run;
+mod layer;
+mod metrics;
+#[cfg(feature = "async")]
+mod async_client;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(feature = "serde")]
+mod codec;
+pub mod protocol;
+pub mod testing;
 
-pub use client::{Client, Response};
-pub use error::TamariError;
-pub use connection::{Connection, TcpConnection};
\ No newline at end of file
+pub use client::{Client, Pipeline, Response};
+pub use error::{ErrorKind, ServerError, TamariError};
+pub use types::{FromTamariValue, ToTamariArg};
+#[cfg(feature = "serde")]
+pub use codec::{Bincode, Codec, Json};
+pub use retry::RetryPolicy;
+pub use pool::{Pool, PoolBuilder, PooledClient};
+pub use parser::{Decoded, ParseError, ResponseDecoder};
+pub use connection::{connect, Connection, TcpConnection, TcpConnectionBuilder};
+#[cfg(unix)]
+pub use connection::UnixConnection;
+pub use record::{RecordingConnection, ReplayConnection};
+pub use dry_run::DryRunConnection;
+pub use layer::{ConnectionStats, Layer, LoggingLayer, MetricsLayer, TimeoutLayer};
+pub use metrics::{Metrics, MetricsSnapshot, Op, OpSnapshot};
+pub use connection_info::{ConnectionAddr, ConnectionInfo, TlsSettings};
+#[cfg(feature = "tls")]
+pub use tls::{TlsConnection, TlsConnectionBuilder};
+#[cfg(feature = "async")]
+pub use async_client::{AsyncClient, AsyncConnection, AsyncTcpConnection, BoxFuture};
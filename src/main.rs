@@ -1,11 +1,14 @@
 use std::env;
-use clap::{Arg, App, SubCommand};
+use clap::{Arg, App, ArgMatches, SubCommand};
 use std::process;
-use std::str;
 use std::io;
-use std::io::Write;
+use std::io::{BufRead, Write};
+use std::time::Duration;
+use std::path::PathBuf;
 
-use tamari;
+mod config;
+
+use config::Config;
 
 fn main() {
     let matches = App::new("tamari-cli")
@@ -18,6 +21,11 @@ fn main() {
                             .value_name("FILE")
                             .help("Sets a custom config file")
                             .takes_value(true))
+                        .arg(Arg::with_name("profile")
+                            .long("profile")
+                            .value_name("NAME")
+                            .help("Selects a named profile from the config file")
+                            .takes_value(true))
                         .arg(Arg::with_name("address")
                             .short("a")
                             .long("address")
@@ -30,12 +38,64 @@ fn main() {
                             .help("Sets the server port")
                             .value_name("port")
                             .takes_value(true))
+                        .arg(Arg::with_name("socket")
+                            .short("s")
+                            .long("socket")
+                            .help("Connects through the Unix domain socket at PATH instead of TCP")
+                            .value_name("PATH")
+                            .conflicts_with_all(&["address", "port", "url"])
+                            .takes_value(true))
+                        .arg(Arg::with_name("url")
+                            .short("u")
+                            .long("url")
+                            .help("Sets the server as a tamari://, tamaris:// or tamari+unix:// URL")
+                            .value_name("URL")
+                            .conflicts_with_all(&["address", "port"])
+                            .takes_value(true))
+                        .arg(Arg::with_name("timeout")
+                            .short("t")
+                            .long("timeout")
+                            .help("Sets the read and write timeout in seconds")
+                            .value_name("secs")
+                            .takes_value(true))
+                        .arg(Arg::with_name("connect_timeout")
+                            .long("connect-timeout")
+                            .help("Sets the connect timeout in seconds")
+                            .value_name("secs")
+                            .takes_value(true))
+                        .arg(Arg::with_name("tls")
+                            .long("tls")
+                            .help("Connects over TLS"))
+                        .arg(Arg::with_name("cacert")
+                            .long("cacert")
+                            .help("Verifies the server against the CA certificates in FILE instead of the bundled Mozilla roots")
+                            .value_name("FILE")
+                            .takes_value(true))
+                        .arg(Arg::with_name("cert")
+                            .long("cert")
+                            .help("Sets the client certificate to present over TLS")
+                            .value_name("FILE")
+                            .requires("key")
+                            .takes_value(true))
+                        .arg(Arg::with_name("key")
+                            .long("key")
+                            .help("Sets the private key of the client certificate")
+                            .value_name("FILE")
+                            .requires("cert")
+                            .takes_value(true))
                         .arg(Arg::with_name("password")
                             .short("w")
                             .long("password")
                             .help("Sets the password to be sent to the server")
                             .value_name("password")
                             .takes_value(true))
+                        .arg(Arg::with_name("format")
+                            .short("f")
+                            .long("format")
+                            .help("Sets the output format")
+                            .value_name("format")
+                            .possible_values(&["display", "raw"])
+                            .takes_value(true))
                         .arg(Arg::with_name("verbose")
                             .short("v")
                             .long("verbose")
@@ -45,7 +105,32 @@ fn main() {
                             .short("d")
                             .long("debug")
                             .multiple(true)
-                            .help("Replaces TCP connection with connection that displays request in protocol format"))
+                            .help("Replaces TCP connection with connection that dumps requests and replies in protocol format"))
+                        .arg(Arg::with_name("debug_reply")
+                            .long("debug-reply")
+                            .help("Sets the next reply of the debug connection, a frame with \\n, \\t, \\\\ and \\xNN escapes such as $3\\tbar\\n")
+                            .value_name("FRAME")
+                            .multiple(true)
+                            .number_of_values(1)
+                            .requires("debug")
+                            .takes_value(true))
+                        .arg(Arg::with_name("record")
+                            .long("record")
+                            .help("Records the session's requests and responses to FILE")
+                            .value_name("FILE")
+                            .conflicts_with("debug")
+                            .takes_value(true))
+                        .arg(Arg::with_name("replay")
+                            .long("replay")
+                            .help("Replaces TCP connection with responses replayed from a recording in FILE")
+                            .value_name("FILE")
+                            .conflicts_with_all(&["debug", "record"])
+                            .takes_value(true))
+                        .arg(Arg::with_name("pipeline")
+                            .long("pipeline")
+                            .help("Reads commands from stdin and sends them in batches of N requests")
+                            .value_name("N")
+                            .takes_value(true))
                         .subcommand(SubCommand::with_name("set")
                                     .about("sets the value at the key")
                                     .arg(Arg::with_name("key")
@@ -57,7 +142,7 @@ fn main() {
                                         .help("the value to set at the key")
                                     ))
                         .subcommand(SubCommand::with_name("get")
-                                    .about("gets the value at the key")
+                                    .about("gets the value at the key, exiting with status 1 if the key doesn't exist")
                                     .arg(Arg::with_name("key")
                                         .required(true)
                                         .help("the key to get the value at")
@@ -66,7 +151,26 @@ fn main() {
                                     .about("deletes the value at the key")
                                     .arg(Arg::with_name("key")
                                         .required(true)
-                                        .help("the key to delete")))       
+                                        .help("the key to delete")))
+                        .subcommand(SubCommand::with_name("mget")
+                                    .about("gets the values at many keys")
+                                    .arg(Arg::with_name("keys")
+                                        .required(true)
+                                        .multiple(true)
+                                        .help("the keys to get the values at")))
+                        .subcommand(SubCommand::with_name("mset")
+                                    .about("sets many values, given as alternating keys and values")
+                                    .arg(Arg::with_name("pairs")
+                                        .required(true)
+                                        .multiple(true)
+                                        .value_names(&["key", "value"])
+                                        .help("the keys and the values to set at them")))
+                        .subcommand(SubCommand::with_name("mdel")
+                                    .about("deletes the values at many keys")
+                                    .arg(Arg::with_name("keys")
+                                        .required(true)
+                                        .multiple(true)
+                                        .help("the keys to delete")))
                         .get_matches();
 
     // check for verbose flag
@@ -74,70 +178,265 @@ fn main() {
 
     let debug = matches.is_present("debug");
 
-    // check for address
-    let address = match matches.value_of("address") {
-        Some(adr) => String::from(adr),
-        None => match env::var("TAMARI_CLI_ADDRESS") {
-            Ok(adr) => adr,
-            Err(_) => String::from("127.0.0.1"),
+    let record = matches.value_of("record");
+    let replay = matches.value_of("replay");
+
+    // load the config file
+    let config = match matches.value_of("config").map(String::from).or_else(|| env::var("TAMARI_CLI_CONFIG").ok()) {
+        Some(path) => match Config::load(&path) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("Failed to load config file {} with error: {}", path, e);
+                process::exit(-1);
+            }
         },
+        None => Config::default(),
     };
 
-    // check for port
-    let port_str = match matches.value_of("port") {
-        Some(pt) => String::from(pt),
-        None => match env::var("TAMARI_CLI_PORT") {
-            Ok(pt) => pt,
-            Err(_) => String::from("8080"),
+    // check for profile
+    let profile = matches.value_of("profile").map(String::from).or_else(|| env::var("TAMARI_CLI_PROFILE").ok());
+
+    if let Some(ref name) = profile {
+        if !config.has_profile(name) {
+            eprintln!("Unknown profile \"{}\"", name);
+            process::exit(-1);
+        }
+    }
+
+    let profile = profile.as_deref();
+
+    // check for connection URL, which stands in for the address, port and password
+    let url = match matches.value_of("url") {
+        Some(url) => Some((String::from(url), Source::CommandLine)),
+        None => env::var("TAMARI_URL").ok().map(|url| (url, Source::Env)),
+    };
+
+    // the server is given by whichever of the URL, the socket and the address
+    // and port comes from the highest source, in that order on a tie
+    let socket = resolve_setting_source(&matches, &config, profile, "socket");
+    let socket_source = socket.as_ref().map(|&(_, source)| source);
+    let tcp_source = ["address", "port"].iter()
+        .filter_map(|name| resolve_setting_source(&matches, &config, profile, name).map(|(_, source)| source))
+        .max();
+
+    let url = url.filter(|&(_, source)| socket_source <= Some(source) && tcp_source <= Some(source));
+    let socket = socket.filter(|&(_, source)| url.is_none() && tcp_source <= Some(source)).map(|(path, _)| path);
+
+    // what the URL sets only gives way to settings from as high a source, so a
+    // password in a --url isn't replaced by one from the environment
+    let url_source = url.as_ref().map(|&(_, source)| source);
+    let overrides_url = |source: Source| url_source.is_none_or(|url_source| url_source <= source);
+
+    let mut info = match url {
+        Some((url, _)) => match tamari::ConnectionInfo::from_url(&url) {
+            Ok(info) => info,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(-1);
+            }
+        },
+        None if socket.is_some() => {
+            tamari::ConnectionInfo::new(tamari::ConnectionAddr::Unix(PathBuf::from(socket.unwrap())))
+        },
+        None => {
+            // check for address
+            let address = resolve_setting(&matches, &config, profile, "address")
+                .unwrap_or_else(|| String::from("127.0.0.1"));
+
+            // check for port
+            let port_str = resolve_setting(&matches, &config, profile, "port")
+                .unwrap_or_else(|| String::from("8080"));
+
+            let port: u16;
+            if let Ok(pt) = port_str.parse::<u16>() {
+                port = pt;
+            } else {
+                // standardize this level of error handling
+                eprintln!("Invalid port");
+                process::exit(-1);
+            };
+
+            tamari::ConnectionInfo::new(tamari::ConnectionAddr::Tcp(address, port))
         },
     };
 
-    let port: u16;
-    if let Ok(pt) = port_str.parse::<u16>() {
-        port = pt;
+    // check for password
+    if let Some((password, source)) = resolve_setting_source(&matches, &config, profile, "password") {
+        if info.password.is_none() || overrides_url(source) {
+            info.password = Some(password).filter(|pw| !pw.is_empty());
+        }
+    }
+
+    // check for timeouts
+    for name in &["timeout", "connect_timeout"] {
+        if let Some((secs, source)) = resolve_setting_source(&matches, &config, profile, name) {
+            let current = match *name {
+                "timeout" => info.timeout,
+                _ => info.connect_timeout,
+            };
+
+            if current.is_some() && !overrides_url(source) {
+                continue;
+            }
+
+            let timeout = match secs.parse::<f64>().ok().and_then(|secs| Duration::try_from_secs_f64(secs).ok()) {
+                Some(timeout) => timeout,
+                None => {
+                    eprintln!("Invalid {} \"{}\"", name.replace('_', " "), secs);
+                    process::exit(-1);
+                }
+            };
+
+            match *name {
+                "timeout" => info.timeout = Some(timeout),
+                _ => info.connect_timeout = Some(timeout),
+            }
+        }
+    }
+
+    // check for TLS, which any of the certificate settings imply, unless they
+    // come from below a URL with a plain scheme
+    let tls = if matches.is_present("tls") {
+        Some(Source::CommandLine)
     } else {
-        // standardize this level of error handling
-        eprintln!("Invalid port");
-        process::exit(-1);
+        match resolve_setting_source(&matches, &config, profile, "tls") {
+            None => None,
+            Some((value, source)) => match &value[..] {
+                "false" | "no" | "0" => None,
+                "true" | "yes" | "1" => Some(source),
+                other => {
+                    eprintln!("Invalid tls setting \"{}\"", other);
+                    process::exit(-1);
+                }
+            },
+        }
     };
 
-    // check for password 
-    /*let password = match matches.value_of("password") {
-        Some(pw) => String::from(pw),
-        None => match env::var("TAMARI_CLI_PASSWORD") {
-            Ok(pw) => pw,
-            Err(_) => String::new(),
-        },
-    };*/
+    let cacert = resolve_setting_source(&matches, &config, profile, "cacert");
+    let cert = resolve_setting_source(&matches, &config, profile, "cert");
+    let key = resolve_setting_source(&matches, &config, profile, "key");
+
+    let source = |setting: &Option<(String, Source)>| setting.as_ref().map(|&(_, source)| source);
+    let sources = [tls, source(&cacert), source(&cert), source(&key)];
 
-    if verbose && !debug {
-        println!("Connecting to server at {}:{} ...", address, port);
+    if info.tls.is_some() || sources.iter().flatten().any(|&source| overrides_url(source)) {
+        let settings = info.tls.get_or_insert_with(tamari::TlsSettings::default);
+
+        for (setting, file) in [(cacert, &mut settings.ca_file), (cert, &mut settings.cert_file), (key, &mut settings.key_file)] {
+            if let Some((path, source)) = setting {
+                if file.is_none() || overrides_url(source) {
+                    *file = Some(PathBuf::from(path));
+                }
+            }
+        }
+    }
+
+    // check for output format
+    let format = match resolve_setting(&matches, &config, profile, "format").as_deref() {
+        None | Some("display") => OutputFormat::Display,
+        Some("raw") => OutputFormat::Raw,
+        Some(other) => {
+            eprintln!("Invalid output format \"{}\"", other);
+            process::exit(-1);
+        }
+    };
+
+    if verbose && replay.is_some() {
+        println!("Replaying recording ...");
+    } else if verbose && !debug {
+        match info.addr {
+            tamari::ConnectionAddr::Tcp(ref address, port) if info.tls.is_some() => {
+                println!("Connecting to server at {}:{} over TLS ...", address, port)
+            },
+            tamari::ConnectionAddr::Tcp(ref address, port) => println!("Connecting to server at {}:{} ...", address, port),
+            tamari::ConnectionAddr::Unix(ref path) => println!("Connecting to server at {} ...", path.display()),
+        }
     } else if verbose {
         println!("Setting up debug connection ...");
     }
 
     let connection: Box<dyn tamari::Connection>;
 
-    if !debug {
-        match tamari::TcpConnection::new(&address, port) {
+    if let Some(path) = replay {
+        match tamari::ReplayConnection::open(path) {
             Ok(c) => connection = Box::new(c),
+            Err(e) => {
+                eprintln!("Failed to load recording {} with error: {}", path, e);
+                process::exit(-1);
+            }
+        }
+    } else if !debug {
+        match tamari::connect(&info) {
+            Ok(c) => connection = c,
             Err(e) => {
                 eprintln!("Failed to connect to server with error: {}", e);
                 process::exit(-1);
             }
         }
     } else {
-        connection = Box::new(DebugConnection{});
+        let mut debug_connection = tamari::DryRunConnection::new();
+
+        for reply in matches.values_of("debug_reply").into_iter().flatten() {
+            match unescape_frame(reply) {
+                Ok(frame) => debug_connection = debug_connection.reply(frame),
+                Err(msg) => {
+                    eprintln!("Invalid debug reply \"{}\": {}", reply, msg);
+                    process::exit(-1);
+                }
+            }
+        }
+
+        connection = Box::new(debug_connection);
     }
 
-    
+    let connection: Box<dyn tamari::Connection> = match record {
+        Some(path) => match tamari::RecordingConnection::create(connection, path) {
+            Ok(c) => Box::new(c),
+            Err(e) => {
+                eprintln!("Failed to create recording {} with error: {}", path, e);
+                process::exit(-1);
+            }
+        },
+        None => connection,
+    };
 
     let mut client = tamari::Client::new(connection);
+    client.set_metrics(tamari::Metrics::new());
+
+    if !debug && replay.is_none() {
+        // long REPL sessions outlive server restarts, so reconnect and retry
+        let reconnect_info = info.clone();
+        let record = record.map(String::from);
+        client.set_reconnect(move || {
+            let connection = tamari::connect(&reconnect_info)?;
+            match record {
+                // reconnects carry on with the same recording
+                Some(ref path) => Ok(Box::new(tamari::RecordingConnection::append(connection, path)?)),
+                None => Ok(connection),
+            }
+        });
+        client.set_retry_policy(tamari::RetryPolicy::default());
+    }
+
+    if let Some(ref password) = info.password {
+        if verbose {
+            println!("Authenticating ...");
+        }
+
+        if let Err(e) = client.authenticate(password) {
+            eprintln!("Failed to authenticate with error: {}", e);
+            process::exit(-1);
+        }
+    }
 
     if let Some(get_matches) = matches.subcommand_matches("get") {
         let key = get_matches.value_of("key").unwrap();
         match client.get(key) {
-            Ok(res) => println!("{}", res),
+            Ok(tamari::Response::NotFound) => {
+                print_response(&tamari::Response::NotFound, format);
+                process::exit(1);
+            },
+            Ok(res) => print_response(&res, format),
             Err(e) => {
                 eprintln!("Get request failed with error: {}", e);
                 process::exit(-1);
@@ -149,7 +448,7 @@ fn main() {
         let key = set_matches.value_of("key").unwrap();
         let value = set_matches.value_of("value").unwrap();
         match client.set(key, value) {
-            Ok(res) => println!("{}", res),
+            Ok(res) => print_response(&res, format),
             Err(e) => {
                 eprintln!("Set request failed with error: {}", e);
                 process::exit(-1);
@@ -160,7 +459,7 @@ fn main() {
     if let Some(del_matches) = matches.subcommand_matches("del") {
         let key = del_matches.value_of("key").unwrap();
         match client.delete(key) {
-            Ok(res) =>println!("{}", res),
+            Ok(res) => print_response(&res, format),
             Err(e) => {
                 eprintln!("Delete request failed with error: {}", e);
                 process::exit(-1);
@@ -168,7 +467,45 @@ fn main() {
         }
     }
 
-    if let None = matches.subcommand_name() {
+    if let Some(mget_matches) = matches.subcommand_matches("mget") {
+        let keys = mget_matches.values_of("keys").unwrap();
+        if print_responses(client.mget(keys), format) {
+            process::exit(-1);
+        }
+    }
+
+    if let Some(mset_matches) = matches.subcommand_matches("mset") {
+        let pairs: Vec<&str> = mset_matches.values_of("pairs").unwrap().collect();
+        if !pairs.len().is_multiple_of(2) {
+            eprintln!("Insufficient number of arguments: mset requires a value for every key");
+            process::exit(-1);
+        }
+
+        if print_responses(client.mset(pairs.chunks(2).map(|pair| (pair[0], pair[1]))), format) {
+            process::exit(-1);
+        }
+    }
+
+    if let Some(mdel_matches) = matches.subcommand_matches("mdel") {
+        let keys = mdel_matches.values_of("keys").unwrap();
+        if print_responses(client.mdel(keys), format) {
+            process::exit(-1);
+        }
+    }
+
+    if let Some(batch_size) = matches.value_of("pipeline") {
+        match batch_size.parse::<usize>() {
+            Ok(n) if 0 < n => run_pipeline(&mut client, n, format),
+            _ => {
+                eprintln!("Invalid pipeline batch size");
+                process::exit(-1);
+            }
+        }
+
+        return;
+    }
+
+    if matches.subcommand_name().is_none() {
         let stdin = io::stdin();
 
         loop {
@@ -177,81 +514,258 @@ fn main() {
             let _ = io::stdout().flush();
 
             match stdin.read_line(&mut buffer) {
-                Ok(_) => process_line(&buffer, &mut client),
+                Ok(0) => break,
+                Ok(_) => process_line(&buffer, &mut client, format),
                 Err(_) => panic!("There was a proplem reading from stdin"),
             };
         }
     }
 }
 
-fn process_line(line: &String, client: &mut tamari::Client) {
-    let mut statement_args: Vec<&str> = line.split_whitespace().collect();
-    
-    match statement_args.get(0) {
-        Some(command) => match &(command.to_lowercase())[..] {
-            "del" => {
-                statement_args.remove(0);
-                if 1 > statement_args.len() {
-                    eprintln!("Insufficient number of arguments: delete requires one argument");
-                    return
-                }
+// Resolves a setting from the command line, then the matching TAMARI_CLI_*
+// environment variable, then the config file.
+fn resolve_setting(matches: &ArgMatches, config: &Config, profile: Option<&str>, name: &str) -> Option<String> {
+    resolve_setting_source(matches, config, profile, name).map(|(value, _)| value)
+}
 
-                match client.delete(statement_args[0]) {
-                    Ok(res) => println!("{}", res),
-                    Err(e) => {
-                        eprintln!("Delete request failed with error: {}", e);
-                        process::exit(-1);
-                    }
-                }
-            },
-            "set" => {
-                statement_args.remove(0);
-                if 2 > statement_args.len() {
-                    eprintln!("Insufficient number of arguments: set requires one argument");
-                    return
-                }
+// Where a setting came from, from lowest to highest priority.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Source {
+    Config,
+    Env,
+    CommandLine,
+}
 
-                match client.set(statement_args[0], statement_args[1]) {
-                    Ok(res) => println!("{}", res),
-                    Err(e) => {
-                        eprintln!("Set request failed with error: {}", e);
-                        process::exit(-1);
-                    }
-                }
-            },
-            "get" => {
-                statement_args.remove(0);
-                if 1 > statement_args.len() {
-                    eprintln!("Insufficient number of arguments: get requires one argument");
-                    return
-                }
+fn resolve_setting_source(matches: &ArgMatches, config: &Config, profile: Option<&str>, name: &str) -> Option<(String, Source)> {
+    if let Some(value) = matches.value_of(name) {
+        return Some((String::from(value), Source::CommandLine));
+    }
 
-                match client.get(statement_args[0]) {
-                    Ok(res) => println!("{}", res),
-                    Err(e) => {
-                        eprintln!("Get request failed with error: {}", e);
-                        return
-                    }
-                }
-            },
-            _ => (),
+    if let Ok(value) = env::var(format!("TAMARI_CLI_{}", name.to_uppercase())) {
+        return Some((value, Source::Env));
+    }
+
+    config.get(profile, name).map(|value| (String::from(value), Source::Config))
+}
+
+#[derive(Clone, Copy)]
+enum OutputFormat {
+    // the response's Display form, with values quoted
+    Display,
+    // the value bytes exactly as received, one per line
+    Raw,
+}
+
+fn print_response(response: &tamari::Response, format: OutputFormat) {
+    match format {
+        OutputFormat::Display => println!("{}", response),
+        OutputFormat::Raw => {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+
+            let _ = match response {
+                tamari::Response::Success | tamari::Response::NotFound => Ok(()),
+                tamari::Response::SuccessWithResult(res) | tamari::Response::Error(res) => {
+                    out.write_all(res).and_then(|_| out.write_all(b"\n"))
+                },
+                tamari::Response::Multi(values) => {
+                    values.iter().try_for_each(|value| out.write_all(value).and_then(|_| out.write_all(b"\n")))
+                },
+            };
+        },
+    }
+}
+
+enum Statement<'a> {
+    Get(&'a str),
+    Set(&'a str, &'a str),
+    Del(&'a str),
+    MGet(Vec<&'a str>),
+    MSet(Vec<(&'a str, &'a str)>),
+    MDel(Vec<&'a str>),
+    // whether to print in the Prometheus text format
+    Stats(bool),
+}
+
+fn parse_statement(line: &str) -> Result<Option<Statement<'_>>, String> {
+    let statement_args: Vec<&str> = line.split_whitespace().collect();
+
+    let command = match statement_args.first() {
+        Some(command) => command.to_lowercase(),
+        None => return Ok(None),
+    };
+
+    let args = &statement_args[1..];
+
+    match &command[..] {
+        "del" => match args.first() {
+            Some(key) => Ok(Some(Statement::Del(key))),
+            None => Err(String::from("Insufficient number of arguments: delete requires one argument")),
+        },
+        "set" => match (args.first(), args.get(1)) {
+            (Some(key), Some(value)) => Ok(Some(Statement::Set(key, value))),
+            _ => Err(String::from("Insufficient number of arguments: set requires two arguments")),
+        },
+        "get" => match args.first() {
+            Some(key) => Ok(Some(Statement::Get(key))),
+            None => Err(String::from("Insufficient number of arguments: get requires one argument")),
+        },
+        "mget" if args.is_empty() => Err(String::from("Insufficient number of arguments: mget requires at least one argument")),
+        "mget" => Ok(Some(Statement::MGet(args.to_vec()))),
+        "mset" if args.is_empty() || !args.len().is_multiple_of(2) => {
+            Err(String::from("Insufficient number of arguments: mset requires a value for every key"))
+        },
+        "mset" => Ok(Some(Statement::MSet(args.chunks(2).map(|pair| (pair[0], pair[1])).collect()))),
+        "mdel" if args.is_empty() => Err(String::from("Insufficient number of arguments: mdel requires at least one argument")),
+        "mdel" => Ok(Some(Statement::MDel(args.to_vec()))),
+        "stats" => match args {
+            [] => Ok(Some(Statement::Stats(false))),
+            ["prometheus"] => Ok(Some(Statement::Stats(true))),
+            _ => Err(String::from("Invalid arguments: stats takes no arguments or \"prometheus\"")),
+        },
+        _ => Ok(None),
+    }
+}
+
+fn process_line(line: &str, client: &mut tamari::Client, format: OutputFormat) {
+    let statement = match parse_statement(line) {
+        Ok(Some(statement)) => statement,
+        Ok(None) => return,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            return
+        }
+    };
+
+    match statement {
+        Statement::Del(key) => match client.delete(key) {
+            Ok(res) => print_response(&res, format),
+            Err(e) => {
+                eprintln!("Delete request failed with error: {}", e);
+                process::exit(-1);
+            }
+        },
+        Statement::Set(key, value) => match client.set(key, value) {
+            Ok(res) => print_response(&res, format),
+            Err(e) => {
+                eprintln!("Set request failed with error: {}", e);
+                process::exit(-1);
+            }
         },
-        
-        None => (),
+        Statement::Get(key) => match client.get(key) {
+            Ok(res) => print_response(&res, format),
+            Err(e) => {
+                eprintln!("Get request failed with error: {}", e);
+            }
+        },
+        Statement::MGet(keys) => { print_responses(client.mget(keys), format); },
+        Statement::MSet(pairs) => { print_responses(client.mset(pairs), format); },
+        Statement::MDel(keys) => { print_responses(client.mdel(keys), format); },
+        Statement::Stats(prometheus) => print_stats(client.metrics(), prometheus),
     }
 }
 
-struct DebugConnection {}
+// Reads statements from stdin and sends them in batches of `batch_size`,
+// printing the responses in order. Exits with an error if any request failed.
+fn run_pipeline(client: &mut tamari::Client, batch_size: usize, format: OutputFormat) {
+    let stdin = io::stdin();
+    let metrics = client.metrics().cloned();
+    let mut pipeline = client.pipeline();
+    let mut failed = false;
+
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => {
+                eprintln!("Failed to read from stdin with error: {}", e);
+                process::exit(-1);
+            }
+        };
+
+        match parse_statement(&line) {
+            Ok(Some(Statement::Get(key))) => { pipeline.get(key); },
+            Ok(Some(Statement::Set(key, value))) => { pipeline.set(key, value); },
+            Ok(Some(Statement::Del(key))) => { pipeline.delete(key); },
+            Ok(Some(Statement::MGet(keys))) => keys.into_iter().for_each(|key| { pipeline.get(key); }),
+            Ok(Some(Statement::MSet(pairs))) => pairs.into_iter().for_each(|(key, value)| { pipeline.set(key, value); }),
+            Ok(Some(Statement::MDel(keys))) => keys.into_iter().for_each(|key| { pipeline.delete(key); }),
+            Ok(Some(Statement::Stats(prometheus))) => {
+                // the stats include everything sent before them
+                failed |= print_responses(pipeline.execute(), format);
+                print_stats(metrics.as_ref(), prometheus);
+            },
+            Ok(None) => (),
+            Err(msg) => {
+                eprintln!("{}", msg);
+                failed = true;
+            }
+        }
 
-impl tamari::Connection for DebugConnection {
-    fn read(&mut self) -> Result<Vec<u8>, tamari::TamariError> {
-        let s = String::from("$\n");
-        Ok(s.into_bytes())
+        if batch_size <= pipeline.len() {
+            failed |= print_responses(pipeline.execute(), format);
+        }
     }
 
-    fn write(&mut self, buffer: &[u8]) -> Result<(), tamari::TamariError> {
-        let s = String::from(str::from_utf8(buffer).unwrap());
-        println!("Debug request: {}", s.escape_debug());
-        Ok(())
+    failed |= print_responses(pipeline.execute(), format);
+
+    if failed {
+        process::exit(-1);
     }
-}
\ No newline at end of file
+}
+
+// Prints each response on its own line and returns whether any request failed.
+fn print_responses(responses: Vec<Result<tamari::Response, tamari::TamariError>>, format: OutputFormat) -> bool {
+    let mut failed = false;
+
+    for response in responses {
+        match response {
+            Ok(res) => print_response(&res, format),
+            Err(e) => {
+                eprintln!("Request failed with error: {}", e);
+                failed = true;
+            }
+        }
+    }
+
+    failed
+}
+
+fn print_stats(metrics: Option<&tamari::Metrics>, prometheus: bool) {
+    match metrics.map(tamari::Metrics::snapshot) {
+        Some(snapshot) if prometheus => print!("{}", snapshot.to_prometheus()),
+        Some(snapshot) => println!("{}", snapshot),
+        None => eprintln!("No metrics are being recorded"),
+    }
+}
+
+// Turns the escapes of a --debug-reply frame into the bytes they stand for.
+fn unescape_frame(frame: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut chars = frame.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some('r') => bytes.push(b'\r'),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(b) if hex.len() == 2 => bytes.push(b),
+                    _ => return Err(format!("invalid escape \\x{}", hex)),
+                }
+            },
+            Some(other) => return Err(format!("invalid escape \\{}", other)),
+            None => return Err(String::from("escape at the end of the frame")),
+        }
+    }
+
+    Ok(bytes)
+}
@@ -0,0 +1,336 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use crate::error::{ErrorKind, TamariError};
+use crate::parser::Response;
+use crate::protocol::Request;
+
+// upper bounds of the latency histogram buckets in seconds, from fast local
+// servers up to requests that are stuck retrying
+const BUCKETS: [f64; 16] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+const OPS: [Op; 3] = [Op::Get, Op::Set, Op::Delete];
+
+/// The operations metrics are kept for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Op {
+    Get,
+    Set,
+    Delete,
+}
+
+impl Op {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Op::Get => "get",
+            Op::Set => "set",
+            Op::Delete => "delete",
+        }
+    }
+
+    fn of(request: &Request) -> Option<Op> {
+        match request {
+            Request::Get(_) => Some(Op::Get),
+            Request::Set(_, _) => Some(Op::Set),
+            Request::Delete(_) => Some(Op::Delete),
+            Request::Auth(_) => None,
+        }
+    }
+}
+
+/// A registry of request counts, error counts and latencies per operation,
+/// filled in by the clients it is given to with
+/// [`Client::set_metrics`](crate::Client::set_metrics).
+///
+/// Clones share the registry, so one can be kept to take snapshots from and
+/// several clients can report into the same one. Latencies are what callers
+/// saw, including retries. Error responses count as `server` errors whether
+/// or not the client has strict errors enabled.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    registry: Arc<Mutex<[OpMetrics; 3]>>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct OpMetrics {
+    requests: u64,
+    errors: BTreeMap<&'static str, u64>,
+    // per bucket, not cumulative, with requests slower than every bound last
+    buckets: [u64; BUCKETS.len() + 1],
+    latency_sum: Duration,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    /// Records the outcome of `request`, unless it is one metrics aren't kept
+    /// for.
+    pub(crate) fn record(&self, request: &Request, latency: Duration, result: &Result<Response, TamariError>) {
+        let op = match Op::of(request) {
+            Some(op) => op,
+            None => return,
+        };
+
+        let error = match result {
+            Ok(Response::Error(_)) => Some("server"),
+            Ok(_) => None,
+            Err(e) => Some(error_label(e.kind())),
+        };
+
+        // counters stay consistent even if a recording thread panicked
+        let mut registry = self.registry.lock().unwrap_or_else(PoisonError::into_inner);
+        let metrics = &mut registry[op as usize];
+
+        metrics.requests += 1;
+        metrics.latency_sum += latency;
+
+        let bucket = BUCKETS.iter().position(|&bound| latency.as_secs_f64() <= bound).unwrap_or(BUCKETS.len());
+        metrics.buckets[bucket] += 1;
+
+        if let Some(error) = error {
+            *metrics.errors.entry(error).or_insert(0) += 1;
+        }
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let registry = self.registry.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let ops = OPS.iter().map(|&op| {
+            let metrics = &registry[op as usize];
+
+            let mut count = 0;
+            let buckets = BUCKETS.iter().zip(&metrics.buckets)
+                .map(|(&bound, &n)| {
+                    count += n;
+                    (bound, count)
+                })
+                .collect();
+
+            OpSnapshot {
+                op,
+                requests: metrics.requests,
+                errors: metrics.errors.clone(),
+                buckets,
+                latency_sum: metrics.latency_sum,
+            }
+        }).collect();
+
+        MetricsSnapshot { ops }
+    }
+}
+
+fn error_label(kind: &ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::IO(_) => "io",
+        ErrorKind::Parse(_) => "parse",
+        ErrorKind::Auth(_) => "auth",
+        ErrorKind::InvalidUrl(_) => "invalid_url",
+        ErrorKind::Timeout(_) => "timeout",
+        ErrorKind::Tls(_) => "tls",
+        ErrorKind::Conversion(_) => "conversion",
+        ErrorKind::Serialization(_) => "serialization",
+        ErrorKind::Server(_) => "server",
+        ErrorKind::Replay(_) => "replay",
+    }
+}
+
+/// The metrics of a [`Metrics`] registry at one point in time.
+///
+/// Its `Display` form is a short summary per operation. Use
+/// [`MetricsSnapshot::to_prometheus`] for the Prometheus text format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsSnapshot {
+    /// One entry per operation, in the order get, set, delete.
+    pub ops: Vec<OpSnapshot>,
+}
+
+/// The metrics of one operation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpSnapshot {
+    pub op: Op,
+    pub requests: u64,
+    /// Failed requests by error kind, such as `io`, `timeout` or `server`.
+    pub errors: BTreeMap<&'static str, u64>,
+    /// The latency histogram as upper bounds in seconds, each with the number
+    /// of requests that took at most that long. Requests slower than the last
+    /// bound are only counted in `requests`.
+    pub buckets: Vec<(f64, u64)>,
+    pub latency_sum: Duration,
+}
+
+impl MetricsSnapshot {
+    pub fn op(&self, op: Op) -> &OpSnapshot {
+        &self.ops[op as usize]
+    }
+
+    /// Formats the metrics in the Prometheus text exposition format, as
+    /// `tamari_client_requests_total`, `tamari_client_errors_total` and the
+    /// `tamari_client_request_duration_seconds` histogram.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP tamari_client_requests_total Requests sent by the client.\n");
+        out.push_str("# TYPE tamari_client_requests_total counter\n");
+        for op in &self.ops {
+            let _ = writeln!(out, "tamari_client_requests_total{{op=\"{}\"}} {}", op.op.name(), op.requests);
+        }
+
+        out.push_str("# HELP tamari_client_errors_total Requests that failed, by error kind.\n");
+        out.push_str("# TYPE tamari_client_errors_total counter\n");
+        for op in &self.ops {
+            for (kind, count) in &op.errors {
+                let _ = writeln!(out, "tamari_client_errors_total{{op=\"{}\",kind=\"{}\"}} {}", op.op.name(), kind, count);
+            }
+        }
+
+        out.push_str("# HELP tamari_client_request_duration_seconds Time taken by requests, including retries.\n");
+        out.push_str("# TYPE tamari_client_request_duration_seconds histogram\n");
+        for op in &self.ops {
+            let name = op.op.name();
+
+            for (bound, count) in &op.buckets {
+                let _ = writeln!(out, "tamari_client_request_duration_seconds_bucket{{op=\"{}\",le=\"{}\"}} {}", name, bound, count);
+            }
+            let _ = writeln!(out, "tamari_client_request_duration_seconds_bucket{{op=\"{}\",le=\"+Inf\"}} {}", name, op.requests);
+            let _ = writeln!(out, "tamari_client_request_duration_seconds_sum{{op=\"{}\"}} {}", name, op.latency_sum.as_secs_f64());
+            let _ = writeln!(out, "tamari_client_request_duration_seconds_count{{op=\"{}\"}} {}", name, op.requests);
+        }
+
+        out
+    }
+}
+
+impl OpSnapshot {
+    /// The number of failed requests, of any kind.
+    pub fn error_count(&self) -> u64 {
+        self.errors.values().sum()
+    }
+
+    /// Estimates the latency that the fraction `q` of requests stayed within,
+    /// e.g. `percentile(0.99)`, by interpolating within the histogram bucket
+    /// it falls in. Estimates past the last bucket are capped at its bound.
+    /// Returns `None` if there were no requests.
+    pub fn percentile(&self, q: f64) -> Option<Duration> {
+        if self.requests == 0 {
+            return None;
+        }
+
+        let rank = q.clamp(0.0, 1.0) * self.requests as f64;
+        let (mut lower, mut below) = (0.0, 0);
+
+        for &(bound, count) in &self.buckets {
+            if rank <= count as f64 && below < count {
+                let fraction = (rank - below as f64) / (count - below) as f64;
+                return Some(Duration::from_secs_f64(lower + (bound - lower) * fraction));
+            }
+
+            lower = bound;
+            below = count;
+        }
+
+        Some(Duration::from_secs_f64(lower))
+    }
+}
+
+impl fmt::Display for MetricsSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, op) in self.ops.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            write!(f, "{}: {} requests", op.op.name(), op.requests)?;
+
+            if op.requests == 0 {
+                continue;
+            }
+
+            write!(f, ", {} errors", op.error_count())?;
+            if !op.errors.is_empty() {
+                let kinds: Vec<String> = op.errors.iter().map(|(kind, count)| format!("{} {}", kind, count)).collect();
+                write!(f, " ({})", kinds.join(", "))?;
+            }
+
+            for &(name, q) in &[("p50", 0.5), ("p90", 0.9), ("p99", 0.99)] {
+                let latency = op.percentile(q).unwrap_or_default();
+                write!(f, ", {} {:.3}ms", name, latency.as_secs_f64() * 1000.0)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    fn get() -> Request {
+        Request::Get(b"foo".to_vec())
+    }
+
+    #[test]
+    fn records_counts_and_errors() {
+        let metrics = Metrics::new();
+        let io_error = TamariError::new(ErrorKind::IO(io::ErrorKind::ConnectionReset.into()));
+
+        metrics.record(&get(), Duration::from_micros(50), &Ok(Response::NotFound));
+        metrics.record(&get(), Duration::from_micros(300), &Err(io_error));
+        metrics.record(&get(), Duration::from_secs(20), &Ok(Response::Error(b"BUSY".to_vec())));
+        metrics.record(&Request::Auth(b"pw".to_vec()), Duration::from_micros(50), &Ok(Response::Success));
+
+        let snapshot = metrics.snapshot();
+        let op = snapshot.op(Op::Get);
+
+        assert_eq!(op.requests, 3);
+        assert_eq!(op.error_count(), 2);
+        assert_eq!(op.errors.get("io"), Some(&1));
+        assert_eq!(op.errors.get("server"), Some(&1));
+        assert_eq!(&op.buckets[..3], &[(0.0001, 1), (0.00025, 1), (0.0005, 2)]);
+        assert_eq!(op.buckets.last(), Some(&(10.0, 2)));
+        assert_eq!(snapshot.op(Op::Set).requests, 0);
+        assert_eq!(snapshot.to_string(), concat!(
+            "get: 3 requests, 2 errors (io 1, server 1), p50 0.375ms, p90 10000.000ms, p99 10000.000ms\n",
+            "set: 0 requests\n",
+            "delete: 0 requests",
+        ));
+    }
+
+    #[test]
+    fn estimates_percentiles() {
+        let metrics = Metrics::new();
+        for _ in 0..4 {
+            metrics.record(&get(), Duration::from_millis(2), &Ok(Response::Success));
+        }
+
+        let op = metrics.snapshot().op(Op::Get).clone();
+
+        // all four fall between the 1ms and 2.5ms bounds
+        assert_eq!(op.percentile(0.5), Some(Duration::from_secs_f64(0.00175)));
+        assert_eq!(op.percentile(1.0), Some(Duration::from_secs_f64(0.0025)));
+        assert_eq!(metrics.snapshot().op(Op::Delete).percentile(0.5), None);
+    }
+
+    #[test]
+    fn prometheus_format() {
+        let metrics = Metrics::new();
+        metrics.record(&Request::Delete(b"foo".to_vec()), Duration::from_millis(2), &Ok(Response::Success));
+
+        let text = metrics.snapshot().to_prometheus();
+
+        assert!(text.contains("# TYPE tamari_client_requests_total counter\ntamari_client_requests_total{op=\"get\"} 0\n"));
+        assert!(text.contains("tamari_client_requests_total{op=\"delete\"} 1\n"));
+        assert!(!text.contains("tamari_client_errors_total{"));
+        assert!(text.contains("tamari_client_request_duration_seconds_bucket{op=\"delete\",le=\"0.001\"} 0\n"));
+        assert!(text.contains("tamari_client_request_duration_seconds_bucket{op=\"delete\",le=\"0.0025\"} 1\n"));
+        assert!(text.contains("tamari_client_request_duration_seconds_bucket{op=\"delete\",le=\"+Inf\"} 1\n"));
+        assert!(text.contains("tamari_client_request_duration_seconds_sum{op=\"delete\"} 0.002\n"));
+        assert!(text.contains("tamari_client_request_duration_seconds_count{op=\"delete\"} 1\n"));
+    }
+}
@@ -2,53 +2,232 @@ use std::fmt;
 use std::error;
 use std::str;
 
-const SUC_PREFIX: char = '$';
-const ERR_PREFIX: char = '!';
+pub(crate) const SUC_PREFIX: char = '$';
+pub(crate) const ERR_PREFIX: char = '!';
+pub(crate) const NOT_FOUND_PREFIX: char = '?';
+const RESPONSE_PREFIXES: &[char] = &[SUC_PREFIX, ERR_PREFIX, NOT_FOUND_PREFIX];
 
 #[derive(PartialEq, Debug)]
 pub enum Response {
     Success,
     SuccessWithResult(Vec<u8>),
     Error(Vec<u8>),
+    /// The key of a get doesn't exist, as opposed to holding an empty value.
+    NotFound,
+    /// A success carrying more than one value, for list-valued results.
+    Multi(Vec<Vec<u8>>),
 }
 
 impl fmt::Display for Response {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Response::Success => write!(f, "Ok"),
+            Response::NotFound => write!(f, "Not found"),
             Response::SuccessWithResult(res) | Response::Error(res) => {
-                match str::from_utf8(&res) {
+                match str::from_utf8(res) {
                     Ok(s) => write!(f, "\"{}\"", s),
                     Err(_) => write!(f, "Recieved non-UTF8 response"),
                 }
             },
+            Response::Multi(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    if 0 < i {
+                        writeln!(f)?;
+                    }
+
+                    match str::from_utf8(value) {
+                        Ok(s) => write!(f, "{}) \"{}\"", i + 1, s)?,
+                        Err(_) => write!(f, "{}) Recieved non-UTF8 response", i + 1)?,
+                    }
+                }
+
+                Ok(())
+            },
         }
     }
 }
 
 pub fn parse_response(raw: &[u8]) -> Result<Response, ParseError>{
-    if 0 < raw.len() {
-        match raw[0] as char {
-            prefix @ SUC_PREFIX | prefix @ ERR_PREFIX => { 
-                let args: Vec<&[u8]>;
-
-                match parse_for_args(&raw[1..]) {
-                    Ok(a) => args = a,
-                    Err(e) =>  return Err(e),
-                }
+    if raw.is_empty() {
+        return Err(ParseError::EmptyResponse);
+    }
+
+    match raw[0] as char {
+        prefix @ SUC_PREFIX | prefix @ ERR_PREFIX | prefix @ NOT_FOUND_PREFIX => {
+            let args = parse_for_args(&raw[1..])?;
+
+            build_response(prefix, &args)
+        },
+        _ => Err(ParseError::InvalidPrefix(String::from(raw[0] as char))),
+    }
+}
+
+/// Outcome of a call to [`ResponseDecoder::decode`].
+#[derive(PartialEq, Debug)]
+pub enum Decoded {
+    /// The frame is not complete yet; at least this many more bytes are needed.
+    Incomplete(usize),
+    /// A complete response along with the number of bytes its frame occupied.
+    Complete(Response, usize),
+    /// A complete frame whose content isn't a valid response, such as an error
+    /// without a message, along with the number of bytes it occupied. The
+    /// frames after it can still be decoded.
+    Invalid(ParseError, usize),
+}
+
+/// Incremental decoder for response frames.
+///
+/// The decoder is handed the bytes received so far, starting at the beginning
+/// of a frame, and remembers how far it got so that each call only looks at the
+/// newly arrived bytes. Callers must only ever append to the buffer between
+/// calls. Once a frame completes, valid or not, the decoder resets and the
+/// caller should drop the consumed bytes from the front of its buffer. An
+/// `Err` means the bytes aren't framed properly, so there is no telling where
+/// the next frame starts.
+#[derive(Debug)]
+pub struct ResponseDecoder {
+    scanner: FrameScanner,
+}
+
+impl ResponseDecoder {
+    pub fn new() -> Self {
+        ResponseDecoder { scanner: FrameScanner::new(RESPONSE_PREFIXES) }
+    }
+
+    pub fn decode(&mut self, buffer: &[u8]) -> Result<Decoded, ParseError> {
+        match self.scanner.scan(buffer)? {
+            Scan::Incomplete(needed) => Ok(Decoded::Incomplete(needed)),
+            Scan::Complete(prefix, args, len) => {
+                let args: Vec<&[u8]> = args.into_iter().map(|(start, end)| &buffer[start..end]).collect();
 
-                match prefix {
-                    SUC_PREFIX if 0 < args.len() => return Ok(Response::SuccessWithResult(args[0].to_vec())),
-                    SUC_PREFIX => return Ok(Response::Success),
-                    ERR_PREFIX if 0 < args.len() => return Ok(Response::Error(args[0].to_vec())),
-                    ERR_PREFIX => return Err(ParseError::MissingArgument),
-                    _ => return Err(ParseError::InvalidPrefix(String::from(prefix))),
+                match build_response(prefix, &args) {
+                    Ok(response) => Ok(Decoded::Complete(response, len)),
+                    Err(e) => Ok(Decoded::Invalid(e, len)),
                 }
-            }, 
-            _ => return Err(ParseError::InvalidPrefix(String::from(raw[0] as char ))),
-        };
-    } else {
-        Err(ParseError::EmptyResponse)
+            },
+        }
+    }
+}
+
+impl Default for ResponseDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+enum FrameState {
+    Prefix,
+    ArgStart,
+    ArgSize { size: usize, digits: usize },
+    ArgBody { start: usize, end: usize },
+}
+
+pub(crate) enum Scan {
+    Incomplete(usize),
+    Complete(char, Vec<(usize, usize)>, usize),
+}
+
+/// Resumable scanner for the framing shared by requests and responses: a
+/// prefix character followed by any number of `<len>\t<bytes>` arguments and a
+/// terminating `\n`. Argument bytes are skipped by length, so they may
+/// themselves contain newlines.
+#[derive(Debug)]
+pub(crate) struct FrameScanner {
+    prefixes: &'static [char],
+    pos: usize,
+    state: FrameState,
+    args: Vec<(usize, usize)>,
+}
+
+impl FrameScanner {
+    pub(crate) fn new(prefixes: &'static [char]) -> Self {
+        FrameScanner { prefixes, pos: 0, state: FrameState::Prefix, args: Vec::new() }
+    }
+
+    fn reset(&mut self) {
+        self.pos = 0;
+        self.state = FrameState::Prefix;
+        self.args.clear();
+    }
+
+    pub(crate) fn scan(&mut self, buffer: &[u8]) -> Result<Scan, ParseError> {
+        let result = self.advance(buffer);
+
+        if let Ok(Scan::Incomplete(_)) = result {
+            return result;
+        }
+
+        self.reset();
+        result
+    }
+
+    fn advance(&mut self, buffer: &[u8]) -> Result<Scan, ParseError> {
+        loop {
+            match self.state {
+                FrameState::Prefix => {
+                    match buffer.first() {
+                        None => return Ok(Scan::Incomplete(1)),
+                        Some(&b) if self.prefixes.contains(&(b as char)) => (),
+                        Some(&b) => return Err(ParseError::InvalidPrefix(String::from(b as char))),
+                    }
+
+                    self.pos = 1;
+                    self.state = FrameState::ArgStart;
+                },
+                FrameState::ArgStart => {
+                    match buffer.get(self.pos) {
+                        None => return Ok(Scan::Incomplete(1)),
+                        Some(b'\n') => {
+                            let args = std::mem::take(&mut self.args);
+                            return Ok(Scan::Complete(buffer[0] as char, args, self.pos + 1));
+                        },
+                        Some(_) => self.state = FrameState::ArgSize { size: 0, digits: 0 },
+                    }
+                },
+                FrameState::ArgSize { size, digits } => {
+                    match buffer.get(self.pos) {
+                        None => return Ok(Scan::Incomplete(1)),
+                        Some(b'\t') if 0 < digits => {
+                            self.pos += 1;
+                            let end = self.pos.checked_add(size).ok_or(ParseError::InvalidArgumentSize)?;
+                            self.state = FrameState::ArgBody { start: self.pos, end };
+                        },
+                        Some(&b) if b.is_ascii_digit() => {
+                            let size = size.checked_mul(10)
+                                .and_then(|size| size.checked_add((b - b'0') as usize))
+                                .ok_or(ParseError::InvalidArgumentSize)?;
+                            self.pos += 1;
+                            self.state = FrameState::ArgSize { size, digits: digits + 1 };
+                        },
+                        Some(_) => return Err(ParseError::InvalidArgumentSize),
+                    }
+                },
+                FrameState::ArgBody { start, end } => {
+                    if buffer.len() < end {
+                        // the remainder of the argument plus at least the terminator
+                        return Ok(Scan::Incomplete(end - buffer.len() + 1));
+                    }
+
+                    self.args.push((start, end));
+                    self.pos = end;
+                    self.state = FrameState::ArgStart;
+                },
+            }
+        }
+    }
+}
+
+fn build_response(prefix: char, args: &[&[u8]]) -> Result<Response, ParseError> {
+    match prefix {
+        SUC_PREFIX if 1 < args.len() => Ok(Response::Multi(args.iter().map(|arg| arg.to_vec()).collect())),
+        SUC_PREFIX if !args.is_empty() => Ok(Response::SuccessWithResult(args[0].to_vec())),
+        SUC_PREFIX => Ok(Response::Success),
+        ERR_PREFIX if !args.is_empty() => Ok(Response::Error(args[0].to_vec())),
+        ERR_PREFIX => Err(ParseError::MissingArgument),
+        NOT_FOUND_PREFIX if !args.is_empty() => Err(ParseError::UnexpectedArgument),
+        NOT_FOUND_PREFIX => Ok(Response::NotFound),
+        _ => Err(ParseError::InvalidPrefix(String::from(prefix))),
     }
 }
 
@@ -56,22 +235,13 @@ fn parse_for_args(raw: &[u8]) -> Result<Vec<&[u8]>, ParseError> {
     let mut args: Vec<&[u8]> = Vec::new();
     let mut mut_raw = raw;
 
-    while mut_raw.len() > 0 {
+    while !mut_raw.is_empty() {
 
         if mut_raw[0] as char == '\n' {
             break;
         }
 
-        let start_index: usize;
-        let end_index: usize;
-
-        match chunk_arg(mut_raw) {
-            Ok((start, end)) => {
-                start_index = start;
-                end_index = end;
-            },
-            Err(e) => return Err(e),
-        }
+        let (start_index, end_index) = chunk_arg(mut_raw)?;
 
         args.push(&mut_raw[start_index..end_index]);
 
@@ -98,11 +268,10 @@ fn chunk_arg(raw: &[u8]) -> Result<(usize, usize), ParseError> {
         i += 1;
     }
 
-    let arg_size: usize;
-    match arg_size_str.parse::<usize>() {
-        Ok(size) => arg_size = size,
+    let arg_size = match arg_size_str.parse::<usize>() {
+        Ok(size) => size,
         Err(_) => return Err(ParseError::InvalidArgumentSize),
-    }
+    };
     
     if raw[i..].len() < arg_size {
         return Err(ParseError::ArgumentSizeTooBig);
@@ -129,6 +298,20 @@ mod tests {
         assert_eq!(parse_response(response).unwrap(), Response::SuccessWithResult(b"foo".to_vec()));
     }
 
+    #[test]
+    fn parse_multi_response() {
+        let response = b"$3\tfoo0\t3\tbar\n";
+
+        assert_eq!(parse_response(response).unwrap(), Response::Multi(vec![b"foo".to_vec(), Vec::new(), b"bar".to_vec()]));
+    }
+
+    #[test]
+    fn display_multi_response() {
+        let response = Response::Multi(vec![b"foo".to_vec(), vec![0xff]]);
+
+        assert_eq!(response.to_string(), "1) \"foo\"\n2) Recieved non-UTF8 response");
+    }
+
     #[test]
     fn parse_error_response() {
         let response = b"!6\tFooBar\n";
@@ -136,18 +319,24 @@ mod tests {
         assert_eq!(parse_response(response).unwrap(), Response::Error(b"FooBar".to_vec()));
     }
 
+    #[test]
+    fn parse_not_found_response() {
+        assert_eq!(parse_response(b"?\n").unwrap(), Response::NotFound);
+        assert_eq!(parse_response(b"?3\tfoo\n"), Err(ParseError::UnexpectedArgument));
+    }
+
     #[test]
     fn parse_invalid_prefix() {
         let response = b"c3\tfoo\n";
 
-        assert_eq!(parse_response(response), Err(ParseError::InvalidPrefix));
+        assert_eq!(parse_response(response), Err(ParseError::InvalidPrefix(String::from("c"))));
     }
 
     #[test]
     fn parse_empty_response() {
         let response = b"";
 
-        assert_eq!(parse_response(response), Err(ParseError::InvalidPrefix));
+        assert_eq!(parse_response(response), Err(ParseError::EmptyResponse));
     }
 
     #[test]
@@ -170,6 +359,56 @@ mod tests {
 
         assert_eq!(parse_response(response), Err(ParseError::ArgumentSizeTooBig));
     }
+
+    #[test]
+    fn decode_complete_frame() {
+        let mut decoder = ResponseDecoder::new();
+
+        assert_eq!(decoder.decode(b"$3\tfoo\n$\n"), Ok(Decoded::Complete(Response::SuccessWithResult(b"foo".to_vec()), 7)));
+        assert_eq!(decoder.decode(b"$\n"), Ok(Decoded::Complete(Response::Success, 2)));
+        assert_eq!(decoder.decode(b"?\n"), Ok(Decoded::Complete(Response::NotFound, 2)));
+    }
+
+    #[test]
+    fn decode_argument_containing_newline() {
+        let mut decoder = ResponseDecoder::new();
+
+        assert_eq!(decoder.decode(b"!7\tfoo\nbar\n"), Ok(Decoded::Complete(Response::Error(b"foo\nbar".to_vec()), 11)));
+    }
+
+    #[test]
+    fn decode_partial_frame() {
+        let frame = b"$10\tfoo\nbar\tba\n";
+        let mut decoder = ResponseDecoder::new();
+
+        assert_eq!(decoder.decode(b""), Ok(Decoded::Incomplete(1)));
+        assert_eq!(decoder.decode(&frame[..1]), Ok(Decoded::Incomplete(1)));
+        assert_eq!(decoder.decode(&frame[..3]), Ok(Decoded::Incomplete(1)));
+        assert_eq!(decoder.decode(&frame[..6]), Ok(Decoded::Incomplete(9)));
+        assert_eq!(decoder.decode(&frame[..13]), Ok(Decoded::Incomplete(2)));
+        assert_eq!(decoder.decode(&frame[..14]), Ok(Decoded::Incomplete(1)));
+        assert_eq!(decoder.decode(&frame[..]), Ok(Decoded::Complete(Response::SuccessWithResult(b"foo\nbar\tba".to_vec()), 15)));
+    }
+
+    #[test]
+    fn decode_resets_after_error() {
+        let mut decoder = ResponseDecoder::new();
+
+        assert_eq!(decoder.decode(b"c3\tfoo\n"), Err(ParseError::InvalidPrefix(String::from("c"))));
+        assert_eq!(decoder.decode(b"$abc\tdef\n"), Err(ParseError::InvalidArgumentSize));
+        assert_eq!(decoder.decode(b"$\tfoo\n"), Err(ParseError::InvalidArgumentSize));
+        assert_eq!(decoder.decode(b"!\n"), Ok(Decoded::Invalid(ParseError::MissingArgument, 2)));
+        assert_eq!(decoder.decode(b"$\n"), Ok(Decoded::Complete(Response::Success, 2)));
+    }
+
+    #[test]
+    fn decode_past_invalid_frame() {
+        let buffer = b"!\n$3\tfoo\n";
+        let mut decoder = ResponseDecoder::new();
+
+        assert_eq!(decoder.decode(buffer), Ok(Decoded::Invalid(ParseError::MissingArgument, 2)));
+        assert_eq!(decoder.decode(&buffer[2..]), Ok(Decoded::Complete(Response::SuccessWithResult(b"foo".to_vec()), 7)));
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -178,6 +417,7 @@ pub enum ParseError {
     MissingArgument,
     ArgumentSizeTooBig,
     InvalidArgumentSize,
+    UnexpectedArgument,
     EmptyResponse,
 }
 
@@ -188,6 +428,7 @@ impl fmt::Display for ParseError {
             ParseError::MissingArgument => write!(f, "missing argument"),
             ParseError::ArgumentSizeTooBig => write!(f, "argument size larger than remaining bytes"),
             ParseError::InvalidArgumentSize => write!(f, "argument size invalid"),
+            ParseError::UnexpectedArgument => write!(f, "unexpected argument"),
             ParseError::EmptyResponse => write!(f, "response is empty"),
         }
     }
@@ -0,0 +1,413 @@
+use std::collections::VecDeque;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use crate::client::Client;
+use crate::error::{ErrorKind, TamariError};
+
+type ClientFactory = Box<dyn Fn() -> Result<Client, TamariError> + Send + Sync>;
+type HealthCheck = Box<dyn Fn(&mut Client) -> bool + Send + Sync>;
+
+/// A pool of clients shared between threads.
+///
+/// [`Pool::get`] checks a client out of the pool, creating a new one with the
+/// pool's factory while there are fewer than `max_size` clients. The returned
+/// [`PooledClient`] derefs to [`Client`] and goes back to the pool when it's
+/// dropped. `Pool` is cheap to clone; clones share the same clients.
+#[derive(Clone)]
+pub struct Pool {
+    inner: Arc<PoolInner>,
+}
+
+struct PoolInner {
+    factory: ClientFactory,
+    health_check: Option<HealthCheck>,
+    min_size: usize,
+    max_size: usize,
+    idle_timeout: Option<Duration>,
+    checkout_timeout: Option<Duration>,
+    state: Mutex<PoolState>,
+    available: Condvar,
+}
+
+struct PoolState {
+    idle: VecDeque<IdleClient>,
+    // every client the pool owns, whether idle or checked out
+    size: usize,
+}
+
+struct IdleClient {
+    client: Client,
+    since: Instant,
+}
+
+impl Pool {
+    pub fn builder<F>(factory: F) -> PoolBuilder
+    where
+        F: Fn() -> Result<Client, TamariError> + Send + Sync + 'static,
+    {
+        PoolBuilder::new(factory)
+    }
+
+    /// Checks a client out of the pool.
+    ///
+    /// Idle clients are reused most recently returned first. Clients that fail
+    /// the health check are dropped on the way, and so are all clients that
+    /// have been idle longer than the idle timeout, here and whenever a client
+    /// is returned. If every client is checked out and the pool is full, this
+    /// blocks until one is returned, failing with `ErrorKind::Timeout` once the
+    /// checkout timeout has passed.
+    pub fn get(&self) -> Result<PooledClient, TamariError> {
+        let inner = &self.inner;
+        let deadline = inner.checkout_timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = inner.lock();
+
+        loop {
+            inner.reap(&mut state);
+
+            if let Some(idle) = state.idle.pop_back() {
+                drop(state);
+
+                let mut client = idle.client;
+                if inner.health_check.as_ref().is_none_or(|check| check(&mut client)) {
+                    return Ok(self.guard(client));
+                }
+
+                state = inner.lock();
+                state.size -= 1;
+                continue;
+            }
+
+            if state.size < inner.max_size {
+                state.size += 1;
+                drop(state);
+
+                return match (inner.factory)() {
+                    Ok(client) => Ok(self.guard(client)),
+                    Err(e) => {
+                        inner.lock().size -= 1;
+                        inner.available.notify_one();
+                        Err(e)
+                    },
+                };
+            }
+
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if deadline <= now {
+                        return Err(TamariError::new(ErrorKind::Timeout(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "timed out waiting for a pooled client",
+                        ))));
+                    }
+
+                    inner.available.wait_timeout(state, deadline - now).unwrap_or_else(PoisonError::into_inner).0
+                },
+                None => inner.available.wait(state).unwrap_or_else(PoisonError::into_inner),
+            };
+        }
+    }
+
+    /// The number of clients the pool owns, idle or checked out.
+    pub fn size(&self) -> usize {
+        self.inner.lock().size
+    }
+
+    /// The number of clients waiting in the pool to be checked out.
+    pub fn idle(&self) -> usize {
+        self.inner.lock().idle.len()
+    }
+
+    fn guard(&self, client: Client) -> PooledClient {
+        PooledClient { pool: Arc::clone(&self.inner), client: Some(client) }
+    }
+}
+
+impl PoolInner {
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        // the state is only ever updated in single steps, so it's still consistent
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Drops the clients idle for longer than the idle timeout, down to
+    // `min_size` clients.
+    fn reap(&self, state: &mut PoolState) {
+        let timeout = match self.idle_timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+
+        // clients are returned onto the back, so the oldest are at the front
+        while self.min_size < state.size && state.idle.front().is_some_and(|idle| timeout <= idle.since.elapsed()) {
+            state.idle.pop_front();
+            state.size -= 1;
+        }
+    }
+}
+
+/// Configures a [`Pool`].
+///
+/// By default the pool holds between 0 and 8 clients, keeps idle clients
+/// forever, and waits for a client to be returned for as long as it takes.
+pub struct PoolBuilder {
+    factory: ClientFactory,
+    health_check: Option<HealthCheck>,
+    min_size: usize,
+    max_size: usize,
+    idle_timeout: Option<Duration>,
+    checkout_timeout: Option<Duration>,
+}
+
+impl PoolBuilder {
+    pub fn new<F>(factory: F) -> Self
+    where
+        F: Fn() -> Result<Client, TamariError> + Send + Sync + 'static,
+    {
+        PoolBuilder {
+            factory: Box::new(factory),
+            health_check: None,
+            min_size: 0,
+            max_size: 8,
+            idle_timeout: None,
+            checkout_timeout: None,
+        }
+    }
+
+    /// Sets the number of clients created up front, which are also kept around
+    /// past the idle timeout.
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Drops clients that have sat in the pool for longer than `timeout`.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Bounds how long [`Pool::get`] waits for a client to be returned.
+    pub fn checkout_timeout(mut self, timeout: Duration) -> Self {
+        self.checkout_timeout = Some(timeout);
+        self
+    }
+
+    /// Runs `check` on idle clients as they're checked out. Clients it returns
+    /// `false` for are dropped.
+    pub fn health_check<F>(mut self, check: F) -> Self
+    where
+        F: Fn(&mut Client) -> bool + Send + Sync + 'static,
+    {
+        self.health_check = Some(Box::new(check));
+        self
+    }
+
+    /// Builds the pool, creating `min_size` clients.
+    pub fn build(self) -> Result<Pool, TamariError> {
+        let max_size = self.max_size.max(1);
+        let min_size = self.min_size.min(max_size);
+
+        let mut idle = VecDeque::with_capacity(max_size);
+        for _ in 0..min_size {
+            idle.push_back(IdleClient { client: (self.factory)()?, since: Instant::now() });
+        }
+
+        let inner = PoolInner {
+            factory: self.factory,
+            health_check: self.health_check,
+            min_size,
+            max_size,
+            idle_timeout: self.idle_timeout,
+            checkout_timeout: self.checkout_timeout,
+            state: Mutex::new(PoolState { idle, size: min_size }),
+            available: Condvar::new(),
+        };
+
+        Ok(Pool { inner: Arc::new(inner) })
+    }
+}
+
+/// A client checked out of a [`Pool`], returned to it on drop.
+pub struct PooledClient {
+    pool: Arc<PoolInner>,
+    client: Option<Client>,
+}
+
+impl PooledClient {
+    /// Drops the client instead of returning it to the pool, for when its
+    /// connection is known to be broken.
+    pub fn discard(mut self) {
+        self.client = None;
+        self.pool.lock().size -= 1;
+        self.pool.available.notify_one();
+    }
+}
+
+impl Deref for PooledClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        self.client.as_ref().expect("pooled client used after discard")
+    }
+}
+
+impl DerefMut for PooledClient {
+    fn deref_mut(&mut self) -> &mut Client {
+        self.client.as_mut().expect("pooled client used after discard")
+    }
+}
+
+impl Drop for PooledClient {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            let mut state = self.pool.lock();
+            state.idle.push_back(IdleClient { client, since: Instant::now() });
+            self.pool.reap(&mut state);
+            drop(state);
+
+            self.pool.available.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::Connection;
+    use crate::Response;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    struct EchoConnection {
+        id: usize,
+    }
+
+    impl Connection for EchoConnection {
+        fn read(&mut self) -> Result<Vec<u8>, TamariError> {
+            Ok(format!("$1\t{}\n", self.id).into_bytes())
+        }
+
+        fn write(&mut self, _buffer: &[u8]) -> Result<(), TamariError> {
+            Ok(())
+        }
+    }
+
+    // A builder whose clients answer every request with their creation number.
+    fn counting_pool() -> PoolBuilder {
+        let created = AtomicUsize::new(0);
+
+        Pool::builder(move || {
+            let id = created.fetch_add(1, Ordering::SeqCst);
+            Ok(Client::new(Box::new(EchoConnection { id })))
+        })
+    }
+
+    fn id(client: &mut Client) -> Vec<u8> {
+        match client.get("id").unwrap() {
+            Response::SuccessWithResult(id) => id,
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+
+    #[test]
+    fn pool_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Pool>();
+    }
+
+    #[test]
+    fn reuses_returned_clients() {
+        let pool = counting_pool().min_size(1).build().unwrap();
+        assert_eq!(pool.size(), 1);
+
+        let first = id(&mut pool.get().unwrap());
+        let second = id(&mut pool.get().unwrap());
+
+        assert_eq!(first, second);
+        assert_eq!(pool.size(), 1);
+        assert_eq!(pool.idle(), 1);
+    }
+
+    #[test]
+    fn checkout_times_out_when_exhausted() {
+        let pool = counting_pool()
+            .max_size(1)
+            .checkout_timeout(Duration::from_millis(20))
+            .build()
+            .unwrap();
+
+        let _client = pool.get().unwrap();
+        let result = pool.get();
+
+        assert!(matches!(result.err().unwrap().kind(), ErrorKind::Timeout(_)));
+    }
+
+    #[test]
+    fn checkout_waits_for_returned_client() {
+        let pool = counting_pool().max_size(1).build().unwrap();
+        let client = pool.get().unwrap();
+
+        let other = pool.clone();
+        let handle = thread::spawn(move || id(&mut other.get().unwrap()));
+
+        thread::sleep(Duration::from_millis(20));
+        drop(client);
+
+        assert_eq!(handle.join().unwrap(), b"0".to_vec());
+    }
+
+    #[test]
+    fn health_check_drops_failing_clients() {
+        let pool = counting_pool()
+            .min_size(1)
+            .health_check(|client| id(client) != b"0".to_vec())
+            .build()
+            .unwrap();
+
+        assert_eq!(id(&mut pool.get().unwrap()), b"1".to_vec());
+        assert_eq!(pool.size(), 1);
+    }
+
+    #[test]
+    fn idle_timeout_drops_stale_clients() {
+        let pool = counting_pool().idle_timeout(Duration::from_millis(10)).build().unwrap();
+
+        drop(pool.get().unwrap());
+        thread::sleep(Duration::from_millis(20));
+
+        assert_eq!(id(&mut pool.get().unwrap()), b"1".to_vec());
+        assert_eq!(pool.size(), 1);
+    }
+
+    #[test]
+    fn idle_timeout_drops_clients_below_fresh_ones() {
+        let pool = counting_pool().idle_timeout(Duration::from_millis(50)).build().unwrap();
+        let older = pool.get().unwrap();
+        let newer = pool.get().unwrap();
+
+        drop(older);
+        thread::sleep(Duration::from_millis(60));
+        drop(newer);
+
+        assert_eq!(pool.size(), 1);
+        assert_eq!(id(&mut pool.get().unwrap()), b"1".to_vec());
+        assert_eq!(pool.size(), 1);
+    }
+
+    #[test]
+    fn discard_frees_a_slot() {
+        let pool = counting_pool().max_size(1).build().unwrap();
+
+        pool.get().unwrap().discard();
+
+        assert_eq!(pool.size(), 0);
+        assert_eq!(id(&mut pool.get().unwrap()), b"1".to_vec());
+    }
+}
@@ -0,0 +1,160 @@
+//! Wire format shared by clients, servers and tools that speak to TamariDB.
+//!
+//! Every frame is a single prefix character, any number of `<len>\t<bytes>`
+//! arguments and a terminating `\n`. Requests use `=` (get), `+` (set),
+//! `-` (delete) and `*` (auth); responses use `$` (success), `!` (error) and
+//! `?` (key not found).
+
+use crate::parser::{FrameScanner, Scan, SUC_PREFIX, ERR_PREFIX, NOT_FOUND_PREFIX};
+
+pub use crate::parser::{parse_response, Decoded, ParseError, Response, ResponseDecoder};
+
+const GET_PREFIX: char = '=';
+const SET_PREFIX: char = '+';
+const DEL_PREFIX: char = '-';
+const AUTH_PREFIX: char = '*';
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Request {
+    Get(Vec<u8>),
+    Set(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+    Auth(Vec<u8>),
+}
+
+/// Encodes a request into its wire representation.
+pub fn encode(request: &Request) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    encode_into(request, &mut buffer);
+    buffer
+}
+
+/// Appends the wire representation of a request to `buffer`.
+pub fn encode_into(request: &Request, buffer: &mut Vec<u8>) {
+    match request {
+        Request::Get(key) => write_frame(buffer, GET_PREFIX, &[key]),
+        Request::Set(key, value) => write_frame(buffer, SET_PREFIX, &[key, value]),
+        Request::Delete(key) => write_frame(buffer, DEL_PREFIX, &[key]),
+        Request::Auth(password) => write_frame(buffer, AUTH_PREFIX, &[password]),
+    }
+}
+
+/// Encodes a response into its wire representation.
+pub fn encode_response(response: &Response) -> Vec<u8> {
+    let mut buffer = Vec::new();
+
+    match response {
+        Response::Success => write_frame(&mut buffer, SUC_PREFIX, &[]),
+        Response::SuccessWithResult(res) => write_frame(&mut buffer, SUC_PREFIX, &[res]),
+        Response::Error(res) => write_frame(&mut buffer, ERR_PREFIX, &[res]),
+        Response::NotFound => write_frame(&mut buffer, NOT_FOUND_PREFIX, &[]),
+        Response::Multi(values) => {
+            let values: Vec<&[u8]> = values.iter().map(|value| &value[..]).collect();
+            write_frame(&mut buffer, SUC_PREFIX, &values)
+        },
+    }
+
+    buffer
+}
+
+/// Decodes the request frame at the start of `raw`.
+///
+/// Returns the request and the number of bytes its frame occupied, or `None`
+/// if `raw` does not hold a complete frame yet.
+pub fn decode_request(raw: &[u8]) -> Result<Option<(Request, usize)>, ParseError> {
+    let mut scanner = FrameScanner::new(&[GET_PREFIX, SET_PREFIX, DEL_PREFIX, AUTH_PREFIX]);
+
+    let (prefix, args, len) = match scanner.scan(raw)? {
+        Scan::Incomplete(_) => return Ok(None),
+        Scan::Complete(prefix, args, len) => (prefix, args, len),
+    };
+
+    let mut args = args.into_iter().map(|(start, end)| raw[start..end].to_vec());
+
+    let request = match prefix {
+        GET_PREFIX => Request::Get(args.next().ok_or(ParseError::MissingArgument)?),
+        SET_PREFIX => {
+            let key = args.next().ok_or(ParseError::MissingArgument)?;
+            let value = args.next().ok_or(ParseError::MissingArgument)?;
+            Request::Set(key, value)
+        },
+        DEL_PREFIX => Request::Delete(args.next().ok_or(ParseError::MissingArgument)?),
+        AUTH_PREFIX => Request::Auth(args.next().ok_or(ParseError::MissingArgument)?),
+        _ => return Err(ParseError::InvalidPrefix(String::from(prefix))),
+    };
+
+    if args.next().is_some() {
+        return Err(ParseError::UnexpectedArgument);
+    }
+
+    Ok(Some((request, len)))
+}
+
+fn write_frame(buffer: &mut Vec<u8>, prefix: char, args: &[&[u8]]) {
+    buffer.push(prefix as u8);
+
+    for arg in args {
+        buffer.extend_from_slice(arg.len().to_string().as_bytes());
+        buffer.push(b'\t');
+        buffer.extend_from_slice(arg);
+    }
+
+    buffer.push(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_requests() {
+        assert_eq!(encode(&Request::Get(b"foo".to_vec())), b"=3\tfoo\n".to_vec());
+        assert_eq!(encode(&Request::Set(b"foo".to_vec(), b"bar".to_vec())), b"+3\tfoo3\tbar\n".to_vec());
+        assert_eq!(encode(&Request::Delete(b"foo".to_vec())), b"-3\tfoo\n".to_vec());
+        assert_eq!(encode(&Request::Auth(b"hunter2".to_vec())), b"*7\thunter2\n".to_vec());
+    }
+
+    #[test]
+    fn encode_responses() {
+        assert_eq!(encode_response(&Response::Success), b"$\n".to_vec());
+        assert_eq!(encode_response(&Response::SuccessWithResult(b"foo".to_vec())), b"$3\tfoo\n".to_vec());
+        assert_eq!(encode_response(&Response::Error(b"FooBar".to_vec())), b"!6\tFooBar\n".to_vec());
+        assert_eq!(encode_response(&Response::NotFound), b"?\n".to_vec());
+        assert_eq!(encode_response(&Response::Multi(vec![b"a".to_vec(), b"bc".to_vec()])), b"$1\ta2\tbc\n".to_vec());
+    }
+
+    #[test]
+    fn decode_encoded_requests() {
+        let requests = vec![
+            Request::Get(b"foo".to_vec()),
+            Request::Set(b"foo\n".to_vec(), b"".to_vec()),
+            Request::Delete(b"foo".to_vec()),
+            Request::Auth(b"hunter2".to_vec()),
+        ];
+
+        for request in requests {
+            let raw = encode(&request);
+            assert_eq!(decode_request(&raw), Ok(Some((request, raw.len()))));
+        }
+    }
+
+    #[test]
+    fn decode_partial_request() {
+        assert_eq!(decode_request(b""), Ok(None));
+        assert_eq!(decode_request(b"+3\tfoo3\tba"), Ok(None));
+        assert_eq!(decode_request(b"+3\tfoo3\tbar"), Ok(None));
+    }
+
+    #[test]
+    fn decode_request_trailing_bytes() {
+        assert_eq!(decode_request(b"=3\tfoo\n-3\tbar\n"), Ok(Some((Request::Get(b"foo".to_vec()), 7))));
+    }
+
+    #[test]
+    fn decode_invalid_request() {
+        assert_eq!(decode_request(b"$3\tfoo\n"), Err(ParseError::InvalidPrefix(String::from("$"))));
+        assert_eq!(decode_request(b"+3\tfoo\n"), Err(ParseError::MissingArgument));
+        assert_eq!(decode_request(b"=3\tfoo3\tbar\n"), Err(ParseError::UnexpectedArgument));
+        assert_eq!(decode_request(b"=x\tfoo\n"), Err(ParseError::InvalidArgumentSize));
+    }
+}
@@ -0,0 +1,418 @@
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::connection::Connection;
+use crate::error::{ErrorKind, TamariError};
+use crate::protocol::{self, Request};
+
+// what auth frames are recorded as, which no client sends since they lack the
+// password
+const REDACTED_AUTH: &[u8] = b"*\n";
+
+/// Wraps a connection and logs everything written to and read from it as
+/// JSON lines, for replaying later with a [`ReplayConnection`].
+///
+/// Every write and every read is logged as its own line, since a pipelined
+/// write is answered by many reads:
+///
+/// ```text
+/// {"ts":1700000000.123456,"op":"write","data":"=3\tfoo\n"}
+/// {"ts":1700000000.124001,"op":"read","data":"$3\tbar\n"}
+/// {"ts":1700000000.130412,"op":"read","error":"IO error: connection reset"}
+/// ```
+///
+/// `ts` is the time in seconds since the Unix epoch. Frames are stored as JSON
+/// strings where every character stands for one byte: printable ASCII as is
+/// and everything else escaped, with bytes above 0x7f as `\u0080` to `ÿ`.
+///
+/// Passwords are kept out of recordings: auth frames are written as `*\n`.
+///
+/// If the log can't be written to, a frame that was already read is still
+/// returned, but every later call fails with an `ErrorKind::IO` error, since
+/// the recording is incomplete.
+pub struct RecordingConnection {
+    inner: Box<dyn Connection>,
+    log: Box<dyn Write + Send>,
+    // why logging failed, once it has
+    failure: Option<String>,
+}
+
+impl RecordingConnection {
+    pub fn new<W: Write + Send + 'static>(inner: Box<dyn Connection>, log: W) -> Self {
+        RecordingConnection { inner, log: Box::new(log), failure: None }
+    }
+
+    /// Records into the file at `path`, replacing any earlier recording.
+    pub fn create<P: AsRef<Path>>(inner: Box<dyn Connection>, path: P) -> Result<Self, TamariError> {
+        Ok(RecordingConnection::new(inner, File::create(path)?))
+    }
+
+    /// Records onto the end of the file at `path`, for connections that carry
+    /// on an earlier recording, such as reconnects.
+    pub fn append<P: AsRef<Path>>(inner: Box<dyn Connection>, path: P) -> Result<Self, TamariError> {
+        Ok(RecordingConnection::new(inner, OpenOptions::new().create(true).append(true).open(path)?))
+    }
+
+    fn log(&mut self, op: &str, field: &str, bytes: &[u8]) -> Result<(), TamariError> {
+        let ts = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+
+        let mut line = format!("{{\"ts\":{:.6},\"op\":\"{}\",\"{}\":\"", ts, op, field);
+        escape(bytes, &mut line);
+        line.push_str("\"}\n");
+
+        // flushed line by line, so a crash still leaves a usable recording
+        let result = self.log.write_all(line.as_bytes()).and_then(|_| self.log.flush());
+        if let Err(ref e) = result {
+            self.failure = Some(e.to_string());
+        }
+
+        Ok(result?)
+    }
+
+    fn check(&self) -> Result<(), TamariError> {
+        match self.failure {
+            Some(ref msg) => Err(TamariError::new(ErrorKind::IO(io::Error::other(format!("recording failed: {}", msg))))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Connection for RecordingConnection {
+    fn read(&mut self) -> Result<Vec<u8>, TamariError> {
+        self.check()?;
+
+        // whatever was read is gone from the connection, so it's returned even
+        // if it can't be logged, and the next call reports the failure
+        match self.inner.read() {
+            Ok(frame) => {
+                let _ = self.log("read", "data", &frame);
+                Ok(frame)
+            },
+            Err(e) => {
+                let _ = self.log("read", "error", e.to_string().as_bytes());
+                Err(e)
+            },
+        }
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> Result<(), TamariError> {
+        self.check()?;
+        self.log("write", "data", &redact(buffer))?;
+        self.inner.write(buffer)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    Write(Vec<u8>),
+    Read(Result<Vec<u8>, String>),
+}
+
+/// Plays back a recording made by a [`RecordingConnection`] without a
+/// server.
+///
+/// Writes must match the recorded writes exactly and in order, except that
+/// any auth frame matches a redacted one, and reads return the recorded
+/// responses. A write that diverges from the recording, or any call past its
+/// end, fails with `ErrorKind::Replay`. Reads that failed while recording fail
+/// again as `ErrorKind::IO` errors.
+#[derive(Debug)]
+pub struct ReplayConnection {
+    events: VecDeque<Event>,
+    // position in the recording, for error messages
+    event: usize,
+}
+
+impl ReplayConnection {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, TamariError> {
+        ReplayConnection::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, TamariError> {
+        let mut events = VecDeque::new();
+
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match parse_event(&line) {
+                Some(event) => events.push_back(event),
+                None => return Err(replay_error(format!("invalid recording on line {}", i + 1))),
+            }
+        }
+
+        Ok(ReplayConnection { events, event: 0 })
+    }
+
+    fn next(&mut self) -> Option<Event> {
+        self.event += 1;
+        self.events.pop_front()
+    }
+}
+
+impl Connection for ReplayConnection {
+    fn read(&mut self) -> Result<Vec<u8>, TamariError> {
+        match self.next() {
+            Some(Event::Read(Ok(frame))) => Ok(frame),
+            Some(Event::Read(Err(msg))) => Err(TamariError::new(ErrorKind::IO(io::Error::other(msg)))),
+            Some(Event::Write(expected)) => Err(replay_error(format!(
+                "read diverges from event {}, which is a write of \"{}\"",
+                self.event,
+                escaped(&expected),
+            ))),
+            None => Err(replay_error(String::from("read past the end of the recording"))),
+        }
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> Result<(), TamariError> {
+        // also keeps passwords out of the error messages
+        let redacted = redact(buffer);
+
+        match self.next() {
+            Some(Event::Write(ref expected)) if expected == buffer || *expected == redacted => Ok(()),
+            Some(Event::Write(expected)) => Err(replay_error(format!(
+                "write of \"{}\" diverges from event {}, which is a write of \"{}\"",
+                escaped(&redacted),
+                self.event,
+                escaped(&expected),
+            ))),
+            Some(Event::Read(_)) => Err(replay_error(format!(
+                "write of \"{}\" diverges from event {}, which is a read",
+                escaped(&redacted),
+                self.event,
+            ))),
+            None => Err(replay_error(format!("write of \"{}\" past the end of the recording", escaped(&redacted)))),
+        }
+    }
+}
+
+// Replaces every auth frame in written bytes with `REDACTED_AUTH`.
+fn redact(buffer: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(buffer.len());
+    let mut rest = buffer;
+
+    while !rest.is_empty() {
+        match protocol::decode_request(rest) {
+            Ok(Some((Request::Auth(_), len))) => {
+                out.extend_from_slice(REDACTED_AUTH);
+                rest = &rest[len..];
+            },
+            Ok(Some((_, len))) => {
+                out.extend_from_slice(&rest[..len]);
+                rest = &rest[len..];
+            },
+            Ok(None) | Err(_) => {
+                out.extend_from_slice(rest);
+                break;
+            },
+        }
+    }
+
+    out
+}
+
+fn replay_error(msg: String) -> TamariError {
+    TamariError::new(ErrorKind::Replay(msg))
+}
+
+fn escaped(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    escape(bytes, &mut out);
+    out
+}
+
+fn escape(bytes: &[u8], out: &mut String) {
+    for &b in bytes {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x20..=0x7e => out.push(b as char),
+            _ => {
+                let _ = write!(out, "\\u{:04x}", b);
+            },
+        }
+    }
+}
+
+// Parses a line written by `RecordingConnection::log`. Only what the recorder
+// writes is supported: a flat object of string and number values.
+fn parse_event(line: &str) -> Option<Event> {
+    let mut chars = line.trim().chars().peekable();
+    let (mut op, mut data, mut error) = (None, None, None);
+
+    if chars.next()? != '{' {
+        return None;
+    }
+
+    loop {
+        skip_whitespace(&mut chars);
+
+        let key = match chars.next()? {
+            '"' => unescape(&mut chars)?,
+            '}' if op.is_none() && data.is_none() && error.is_none() => break,
+            _ => return None,
+        };
+
+        skip_whitespace(&mut chars);
+        if chars.next()? != ':' {
+            return None;
+        }
+        skip_whitespace(&mut chars);
+
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            let value = unescape(&mut chars)?;
+
+            match &key[..] {
+                b"op" => op = Some(value),
+                b"data" => data = Some(value),
+                b"error" => error = Some(value),
+                _ => (),
+            }
+        } else {
+            // numbers, like the timestamp, aren't needed for replaying
+            while chars.peek().is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(*c)) {
+                chars.next();
+            }
+        }
+
+        skip_whitespace(&mut chars);
+        match chars.next()? {
+            ',' => continue,
+            '}' => break,
+            _ => return None,
+        }
+    }
+
+    match (op.as_deref()?, data, error) {
+        (b"write", Some(data), None) => Some(Event::Write(data)),
+        (b"read", Some(data), None) => Some(Event::Read(Ok(data))),
+        (b"read", None, Some(error)) => Some(Event::Read(Err(String::from_utf8_lossy(&error).into_owned()))),
+        _ => None,
+    }
+}
+
+fn skip_whitespace<I: Iterator<Item = char>>(chars: &mut std::iter::Peekable<I>) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+// Reads the rest of a string whose opening quote was already consumed, turning
+// every character back into the byte it stands for.
+fn unescape<I: Iterator<Item = char>>(chars: &mut I) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+
+    loop {
+        let c = match chars.next()? {
+            '"' => return Some(bytes),
+            '\\' => match chars.next()? {
+                '"' => '"',
+                '\\' => '\\',
+                '/' => '/',
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'b' => '\u{8}',
+                'f' => '\u{c}',
+                'u' => {
+                    let hex: String = chars.take(4).collect();
+                    char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                },
+                _ => return None,
+            },
+            c => c,
+        };
+
+        bytes.push(u8::try_from(u32::from(c)).ok()?);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::dry_run::DryRunConnection;
+    use crate::parser::Response;
+    use crate::testing::{FailingOutput, FakeServer};
+    use crate::TcpConnection;
+
+    #[test]
+    fn escape_round_trip() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let line = format!("{{\"ts\":1.5,\"op\":\"read\",\"data\":\"{}\"}}", escaped(&bytes));
+
+        assert!(line.is_ascii());
+        assert_eq!(parse_event(&line), Some(Event::Read(Ok(bytes))));
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let path = std::env::temp_dir().join(format!("tamari-test-{}.jsonl", std::process::id()));
+        let server = FakeServer::tcp().unwrap();
+        server.insert("foo", vec![0u8, 0xff, b'\n']);
+
+        let connection = TcpConnection::from_info(server.info()).unwrap();
+        let mut client = Client::new(Box::new(RecordingConnection::create(Box::new(connection), &path).unwrap()));
+        let recorded = (client.get("foo").unwrap(), client.mget(vec!["bar", "foo"]));
+        drop(server);
+
+        let mut client = Client::new(Box::new(ReplayConnection::open(&path).unwrap()));
+
+        assert_eq!(client.get("foo").unwrap(), recorded.0);
+        assert_eq!(client.get("foo").unwrap_err().to_string(), "Replay error: write of \"=3\\tfoo\\n\" diverges from event 3, which is a write of \"=3\\tbar\\n=3\\tfoo\\n\"");
+        assert_eq!(recorded.0, Response::SuccessWithResult(vec![0, 0xff, b'\n']));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn record_redacts_passwords() {
+        let path = std::env::temp_dir().join(format!("tamari-test-{}-auth.jsonl", std::process::id()));
+        let server = FakeServer::tcp().unwrap();
+        server.set_password(Some("hunter2"));
+
+        let connection = TcpConnection::from_info(server.info()).unwrap();
+        let mut client = Client::new(Box::new(RecordingConnection::create(Box::new(connection), &path).unwrap()));
+        client.authenticate("hunter2").unwrap();
+        client.set("foo", "bar").unwrap();
+        drop(server);
+
+        let recording = std::fs::read_to_string(&path).unwrap();
+        assert!(!recording.contains("hunter2"), "{}", recording);
+
+        let mut client = Client::new(Box::new(ReplayConnection::open(&path).unwrap()));
+        client.authenticate("anything").unwrap();
+        assert_eq!(client.set("foo", "bar").unwrap(), Response::Success);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn keeps_frames_when_logging_fails() {
+        let inner = DryRunConnection::with_output(io::sink()).reply(&b"$3\tbar\n"[..]);
+        let mut connection = RecordingConnection::new(Box::new(inner), FailingOutput(1));
+
+        connection.write(b"=3\tfoo\n").unwrap();
+        assert_eq!(connection.read().unwrap(), b"$3\tbar\n");
+
+        let error = connection.write(b"=3\tfoo\n").unwrap_err();
+        assert_eq!(error.to_string(), "IO error: recording failed: disk full");
+    }
+
+    #[test]
+    fn replay_rejects_invalid_recordings() {
+        let result = ReplayConnection::from_reader(&b"{\"op\":\"write\",\"data\":\"=3\\tfoo\\n\"}\n{\"op\":\"read\"}\n"[..]);
+
+        assert!(matches!(result.unwrap_err().kind(), ErrorKind::Replay(msg) if msg.ends_with("line 2")));
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// How a [`Client`](crate::Client) retries requests that fail because the
+/// connection broke or timed out.
+///
+/// Before every retry the client waits out an exponential backoff and then
+/// rebuilds its connection with its reconnect factory, so retries only happen
+/// on clients that have one. Gets and deletes are retried; sets are only
+/// retried after opting in with [`RetryPolicy::retry_sets`], since a set may
+/// have been applied before the connection failed.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    retry_sets: bool,
+}
+
+impl RetryPolicy {
+    /// A policy that makes at most `max_attempts` attempts per request,
+    /// including the first one.
+    pub fn new(max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            base_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(2),
+            jitter: true,
+            retry_sets: false,
+        }
+    }
+
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        RetryPolicy::new(1)
+    }
+
+    /// Sets the delay before the first retry. Each further retry doubles it.
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    /// Caps the delay between two attempts.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Randomises each delay to between half and all of its backoff, so that
+    /// many clients losing the same server don't reconnect in lockstep.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Allows sets to be retried as well.
+    pub fn retry_sets(mut self, retry_sets: bool) -> Self {
+        self.retry_sets = retry_sets;
+        self
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub fn retries_sets(&self) -> bool {
+        self.retry_sets
+    }
+
+    /// The delay to wait before retry number `retry`, counting from 1.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+
+        if self.jitter {
+            delay / 2 + delay.mul_f64(random_fraction() / 2.0)
+        } else {
+            delay
+        }
+    }
+}
+
+/// Three attempts, starting at 50ms and backing off to at most 2s, with jitter.
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new(3)
+    }
+}
+
+// A random number in [0, 1). `RandomState` is seeded randomly per instance,
+// which is plenty for spreading out reconnects without pulling in a crate.
+fn random_fraction() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exponential_backoff() {
+        let policy = RetryPolicy::new(5)
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(300))
+            .jitter(false);
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(300));
+        assert_eq!(policy.backoff(40), Duration::from_millis(300));
+    }
+
+    #[test]
+    fn jittered_backoff() {
+        let policy = RetryPolicy::new(5).base_delay(Duration::from_millis(100));
+
+        for _ in 0..100 {
+            let delay = policy.backoff(2);
+            assert!(Duration::from_millis(100) <= delay && delay <= Duration::from_millis(200));
+        }
+    }
+}
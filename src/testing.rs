@@ -0,0 +1,320 @@
+//! Test doubles for code that talks to TamariDB.
+//!
+//! [`FakeServer`] speaks the real protocol over a local socket, so clients,
+//! connections and the CLI can be tested end to end without a real server.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::net;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+use crate::connection_info::{ConnectionAddr, ConnectionInfo};
+use crate::parser::Response;
+use crate::protocol::{self, Request};
+use crate::types::ToTamariArg;
+
+const READ_CHUNK_SIZE: usize = 4096;
+
+// not a valid response frame, whatever the client expects
+const CORRUPT_FRAME: &[u8] = b"%corrupt\n";
+
+/// A fault injected into the server's handling of a request.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    /// Waits this long before handling the request.
+    Latency(Duration),
+    /// Closes the connection without handling the request.
+    Drop,
+    /// Responds with bytes that aren't a valid frame, without handling the
+    /// request.
+    Corrupt,
+    /// Responds with an error carrying this message, without handling the
+    /// request.
+    Error(String),
+    /// Responds with these bytes as they are, without handling the request.
+    Raw(Vec<u8>),
+}
+
+/// An in-process TamariDB server backed by an in-memory map.
+///
+/// Gets of missing keys, and deletes of them, are answered with
+/// `Response::NotFound`. Faults queued with [`FakeServer::inject`] are applied
+/// one per request, in the order requests arrive on any connection.
+///
+/// The listener stops when the server is dropped. Connections that are still
+/// open are served until the client closes them.
+pub struct FakeServer {
+    info: ConnectionInfo,
+    shared: Arc<Shared>,
+    listener: Option<thread::JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    shutdown: AtomicBool,
+}
+
+struct State {
+    data: HashMap<Vec<u8>, Vec<u8>>,
+    faults: VecDeque<Fault>,
+    latency: Duration,
+    password: Option<Vec<u8>>,
+}
+
+impl FakeServer {
+    /// Starts a server on a free port on the loopback interface.
+    pub fn tcp() -> io::Result<FakeServer> {
+        let listener = net::TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let shared = Shared::new();
+
+        let accepting = Arc::clone(&shared);
+        let handle = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if accepting.shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                if let Ok(stream) = stream {
+                    let _ = stream.set_nodelay(true);
+                    accepting.spawn_connection(stream);
+                }
+            }
+        });
+
+        let info = ConnectionInfo::new(ConnectionAddr::Tcp(addr.ip().to_string(), addr.port()));
+
+        Ok(FakeServer { info, shared, listener: Some(handle) })
+    }
+
+    /// Starts a server on a Unix domain socket at `path`, which is removed
+    /// again when the server is dropped.
+    #[cfg(unix)]
+    pub fn unix<P: Into<PathBuf>>(path: P) -> io::Result<FakeServer> {
+        let path = path.into();
+        let listener = UnixListener::bind(&path)?;
+        let shared = Shared::new();
+
+        let accepting = Arc::clone(&shared);
+        let handle = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if accepting.shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                if let Ok(stream) = stream {
+                    accepting.spawn_connection(stream);
+                }
+            }
+        });
+
+        let info = ConnectionInfo::new(ConnectionAddr::Unix(path));
+
+        Ok(FakeServer { info, shared, listener: Some(handle) })
+    }
+
+    /// Where the server can be reached.
+    pub fn info(&self) -> &ConnectionInfo {
+        &self.info
+    }
+
+    /// The server's address as a `tamari://` or `tamari+unix://` URL.
+    pub fn url(&self) -> String {
+        match self.info.addr {
+            ConnectionAddr::Tcp(ref addr, port) => format!("tamari://{}:{}", addr, port),
+            ConnectionAddr::Unix(ref path) => format!("tamari+unix://{}", path.display()),
+        }
+    }
+
+    /// Requires connections to authenticate with `password` before anything
+    /// else. Other requests are rejected with a `NOAUTH` error until then, and
+    /// wrong passwords with a `WRONGPASS` error.
+    pub fn set_password(&self, password: Option<&str>) {
+        self.shared.lock().password = password.map(|pw| pw.as_bytes().to_vec());
+    }
+
+    /// Waits `latency` before handling every request.
+    pub fn set_latency(&self, latency: Duration) {
+        self.shared.lock().latency = latency;
+    }
+
+    /// Queues a fault for the next request that doesn't have one yet.
+    pub fn inject(&self, fault: Fault) {
+        self.shared.lock().faults.push_back(fault);
+    }
+
+    pub fn insert(&self, key: impl ToTamariArg, value: impl ToTamariArg) {
+        self.shared.lock().data.insert(key.to_tamari_arg(), value.to_tamari_arg());
+    }
+
+    pub fn get(&self, key: impl ToTamariArg) -> Option<Vec<u8>> {
+        self.shared.lock().data.get(&key.to_tamari_arg()).cloned()
+    }
+}
+
+impl Drop for FakeServer {
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::SeqCst);
+
+        // wake the listener up so that it sees the shutdown flag
+        match self.info.addr {
+            ConnectionAddr::Tcp(ref addr, port) => drop(net::TcpStream::connect((&addr[..], port))),
+            #[cfg(unix)]
+            ConnectionAddr::Unix(ref path) => drop(UnixStream::connect(path)),
+            #[cfg(not(unix))]
+            ConnectionAddr::Unix(_) => (),
+        }
+
+        if let Some(handle) = self.listener.take() {
+            let _ = handle.join();
+        }
+
+        #[cfg(unix)]
+        if let ConnectionAddr::Unix(ref path) = self.info.addr {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+impl Shared {
+    fn new() -> Arc<Shared> {
+        let state = State { data: HashMap::new(), faults: VecDeque::new(), latency: Duration::ZERO, password: None };
+
+        Arc::new(Shared { state: Mutex::new(state), shutdown: AtomicBool::new(false) })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // a panicking connection thread can't leave the map half updated
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn spawn_connection<S: Read + Write + Send + 'static>(self: &Arc<Self>, stream: S) {
+        let shared = Arc::clone(self);
+        thread::spawn(move || {
+            let _ = shared.serve(stream);
+        });
+    }
+
+    fn serve<S: Read + Write>(&self, mut stream: S) -> io::Result<()> {
+        let mut buffer = Vec::new();
+        let mut chunk = [0; READ_CHUNK_SIZE];
+        let mut authenticated = false;
+
+        loop {
+            let (request, len) = match protocol::decode_request(&buffer) {
+                Ok(Some(decoded)) => decoded,
+                Ok(None) => match stream.read(&mut chunk)? {
+                    0 => return Ok(()),
+                    n => {
+                        buffer.extend_from_slice(&chunk[..n]);
+                        continue;
+                    },
+                },
+                Err(e) => {
+                    let msg = format!("PROTOCOL {}", e);
+                    return stream.write_all(&protocol::encode_response(&Response::Error(msg.into_bytes())));
+                },
+            };
+
+            buffer.drain(..len);
+
+            let (fault, latency) = {
+                let mut state = self.lock();
+                (state.faults.pop_front(), state.latency)
+            };
+
+            thread::sleep(latency);
+
+            let response = match fault {
+                Some(Fault::Latency(delay)) => {
+                    thread::sleep(delay);
+                    protocol::encode_response(&self.handle(request, &mut authenticated))
+                },
+                Some(Fault::Drop) => return Ok(()),
+                Some(Fault::Corrupt) => CORRUPT_FRAME.to_vec(),
+                Some(Fault::Error(msg)) => protocol::encode_response(&Response::Error(msg.into_bytes())),
+                Some(Fault::Raw(bytes)) => bytes,
+                None => protocol::encode_response(&self.handle(request, &mut authenticated)),
+            };
+
+            stream.write_all(&response)?;
+        }
+    }
+
+    fn handle(&self, request: Request, authenticated: &mut bool) -> Response {
+        let mut state = self.lock();
+
+        match request {
+            Request::Auth(password) => match state.password {
+                Some(ref expected) if *expected != password => Response::Error(b"WRONGPASS invalid password".to_vec()),
+                _ => {
+                    *authenticated = true;
+                    Response::Success
+                },
+            },
+            _ if state.password.is_some() && !*authenticated => Response::Error(b"NOAUTH authentication required".to_vec()),
+            Request::Get(key) => match state.data.get(&key) {
+                Some(value) => Response::SuccessWithResult(value.clone()),
+                None => Response::NotFound,
+            },
+            Request::Set(key, value) => {
+                state.data.insert(key, value);
+                Response::Success
+            },
+            Request::Delete(key) => match state.data.remove(&key) {
+                Some(_) => Response::Success,
+                None => Response::NotFound,
+            },
+        }
+    }
+}
+
+// Collects everything written to it, for checking what a connection printed.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct Output(Arc<Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Output {
+    pub(crate) fn text(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+// Accepts that many writes, then fails as if the disk were full.
+#[cfg(test)]
+pub(crate) struct FailingOutput(pub(crate) usize);
+
+#[cfg(test)]
+impl Write for FailingOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.0 {
+            0 => Err(io::Error::other("disk full")),
+            _ => {
+                self.0 -= 1;
+                Ok(buf.len())
+            },
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,374 @@
+use std::convert::TryFrom;
+use std::io;
+use std::net;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use crate::connection::{Connection, FrameBuffer, TcpConnectionBuilder};
+use crate::connection_info::{ConnectionAddr, ConnectionInfo, TlsSettings};
+use crate::error::{ErrorKind, TamariError};
+
+/// A TCP connection secured with TLS, for servers behind a TLS terminator.
+pub struct TlsConnection {
+    stream: StreamOwned<ClientConnection, net::TcpStream>,
+    frames: FrameBuffer,
+}
+
+impl Connection for TlsConnection {
+    fn read(&mut self) -> Result<Vec<u8>, TamariError> {
+        self.frames.read_frame(&mut self.stream)
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> Result<(), TamariError> {
+        // records are only sent once flushed, which write_frames takes care of
+        self.frames.write_frames(&mut self.stream, buffer)
+    }
+}
+
+impl TlsConnection {
+    pub fn builder(addr: &str, port: u16) -> TlsConnectionBuilder {
+        TlsConnectionBuilder::new(addr, port)
+    }
+
+    /// Connects to the server described by a `tamaris://` URL. Any password in
+    /// the URL is ignored here; use `Client::from_url` to authenticate as well.
+    pub fn from_url(url: &str) -> Result<Self, TamariError> {
+        TlsConnection::from_info(&ConnectionInfo::from_url(url)?)
+    }
+
+    /// Connects with the TLS settings of `info`, or the default settings if it
+    /// has none.
+    pub fn from_info(info: &ConnectionInfo) -> Result<Self, TamariError> {
+        let mut builder = match info.addr {
+            ConnectionAddr::Tcp(ref addr, port) => TlsConnection::builder(addr, port),
+            ConnectionAddr::Unix(_) => {
+                return Err(TamariError::new(ErrorKind::InvalidUrl(String::from("not a TCP address"))));
+            },
+        };
+
+        if let Some(ref settings) = info.tls {
+            builder.settings = settings.clone();
+        }
+
+        if let Some(timeout) = info.timeout {
+            builder = builder.read_timeout(timeout).write_timeout(timeout);
+        }
+
+        if let Some(timeout) = info.connect_timeout.or(info.timeout) {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        builder.connect()
+    }
+}
+
+/// Configures a [`TlsConnection`] before connecting.
+///
+/// The server certificate is verified against the bundled Mozilla root
+/// certificates unless a CA file is given, and the server name used for SNI
+/// and verification defaults to the address being connected to.
+#[derive(Debug, Clone)]
+pub struct TlsConnectionBuilder {
+    tcp: TcpConnectionBuilder,
+    addr: String,
+    settings: TlsSettings,
+}
+
+impl TlsConnectionBuilder {
+    pub fn new(addr: &str, port: u16) -> Self {
+        TlsConnectionBuilder {
+            tcp: TcpConnectionBuilder::new(addr, port),
+            addr: String::from(addr),
+            settings: TlsSettings::default(),
+        }
+    }
+
+    /// Bounds the time spent establishing the TCP connection, per resolved
+    /// address.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.tcp = self.tcp.connect_timeout(timeout);
+        self
+    }
+
+    /// Bounds the time a single read from the server may block, including
+    /// reads during the handshake.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.tcp = self.tcp.read_timeout(timeout);
+        self
+    }
+
+    /// Bounds the time a single write to the server may block, including
+    /// writes during the handshake.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.tcp = self.tcp.write_timeout(timeout);
+        self
+    }
+
+    /// Trusts only the PEM encoded CA certificates in `path`.
+    pub fn ca_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.settings.ca_file = Some(path.into());
+        self
+    }
+
+    /// Authenticates to the server with the PEM encoded certificate chain in
+    /// `cert` and the private key in `key`.
+    pub fn client_cert<C: Into<PathBuf>, K: Into<PathBuf>>(mut self, cert: C, key: K) -> Self {
+        self.settings.cert_file = Some(cert.into());
+        self.settings.key_file = Some(key.into());
+        self
+    }
+
+    /// Sets the name sent for SNI and expected in the server certificate.
+    pub fn server_name(mut self, name: &str) -> Self {
+        self.settings.server_name = Some(String::from(name));
+        self
+    }
+
+    /// Connects and completes the handshake, so that an untrusted certificate
+    /// is reported here rather than by the first request.
+    pub fn connect(self) -> Result<TlsConnection, TamariError> {
+        let config = client_config(&self.settings)?;
+
+        let name = self.settings.server_name.as_deref().unwrap_or(&self.addr);
+        let server_name = match ServerName::try_from(String::from(name)) {
+            Ok(server_name) => server_name,
+            Err(_) => return Err(tls_error(format!("invalid server name \"{}\"", name))),
+        };
+
+        let session = match ClientConnection::new(config, server_name) {
+            Ok(session) => session,
+            Err(e) => return Err(tls_error(e.to_string())),
+        };
+
+        let mut stream = StreamOwned::new(session, self.tcp.connect_stream()?);
+
+        while stream.conn.is_handshaking() {
+            if let Err(e) = stream.conn.complete_io(&mut stream.sock) {
+                return Err(handshake_error(e));
+            }
+        }
+
+        Ok(TlsConnection { stream, frames: FrameBuffer::new() })
+    }
+}
+
+fn client_config(settings: &TlsSettings) -> Result<Arc<ClientConfig>, TamariError> {
+    let mut roots = RootCertStore::empty();
+
+    match settings.ca_file {
+        Some(ref path) => {
+            for cert in load_certs(path)? {
+                if let Err(e) = roots.add(cert) {
+                    return Err(tls_error(format!("invalid CA certificate in {}: {}", path.display(), e)));
+                }
+            }
+        },
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+
+    let builder = match ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+    {
+        Ok(builder) => builder.with_root_certificates(roots),
+        Err(e) => return Err(tls_error(e.to_string())),
+    };
+
+    let config = match (&settings.cert_file, &settings.key_file) {
+        (Some(cert), Some(key)) => match builder.with_client_auth_cert(load_certs(cert)?, load_key(key)?) {
+            Ok(config) => config,
+            Err(e) => return Err(tls_error(e.to_string())),
+        },
+        (None, None) => builder.with_no_client_auth(),
+        _ => return Err(tls_error(String::from("a client certificate and its key must be given together"))),
+    };
+
+    Ok(Arc::new(config))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TamariError> {
+    let certs = match CertificateDer::pem_file_iter(path) {
+        Ok(certs) => certs.collect::<Result<Vec<_>, _>>(),
+        Err(e) => Err(e),
+    };
+
+    match certs {
+        Ok(ref certs) if certs.is_empty() => Err(tls_error(format!("no certificates in {}", path.display()))),
+        Ok(certs) => Ok(certs),
+        Err(e) => Err(tls_error(format!("failed to read certificates from {}: {}", path.display(), e))),
+    }
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, TamariError> {
+    match PrivateKeyDer::from_pem_file(path) {
+        Ok(key) => Ok(key),
+        Err(e) => Err(tls_error(format!("failed to read private key from {}: {}", path.display(), e))),
+    }
+}
+
+// rustls reports protocol failures, like an untrusted certificate, as
+// `InvalidData`; anything else is a problem with the socket itself.
+fn handshake_error(err: io::Error) -> TamariError {
+    match err.kind() {
+        io::ErrorKind::InvalidData => tls_error(err.to_string()),
+        _ => TamariError::from(err),
+    }
+}
+
+fn tls_error(msg: String) -> TamariError {
+    TamariError::new(ErrorKind::Tls(msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::crypto::CryptoProvider;
+    use rustls::server::WebPkiClientVerifier;
+    use rustls::{ServerConfig, ServerConnection};
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::thread;
+
+    struct Identity {
+        cert: PathBuf,
+        key: PathBuf,
+    }
+
+    // A self-signed certificate for "localhost", written to PEM files.
+    fn identity(name: &str) -> Identity {
+        let certified = rcgen::generate_simple_self_signed(vec![String::from("localhost")]).unwrap();
+        let dir = std::env::temp_dir();
+        let identity = Identity {
+            cert: dir.join(format!("tamari-test-{}-{}.crt", std::process::id(), name)),
+            key: dir.join(format!("tamari-test-{}-{}.key", std::process::id(), name)),
+        };
+
+        fs::write(&identity.cert, certified.cert.pem()).unwrap();
+        fs::write(&identity.key, certified.key_pair.serialize_pem()).unwrap();
+
+        identity
+    }
+
+    impl Identity {
+        fn remove(self) {
+            fs::remove_file(self.cert).unwrap();
+            fs::remove_file(self.key).unwrap();
+        }
+    }
+
+    fn provider() -> Arc<CryptoProvider> {
+        Arc::new(rustls::crypto::ring::default_provider())
+    }
+
+    // What the server saw of the client.
+    #[derive(Debug, PartialEq)]
+    struct Peer {
+        server_name: Option<String>,
+        has_cert: bool,
+    }
+
+    // Answers a single get over TLS, returning `None` if the handshake failed.
+    fn serve(config: ServerConfig) -> (u16, thread::JoinHandle<Option<Peer>>) {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = thread::spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            let session = ServerConnection::new(Arc::new(config)).unwrap();
+            let mut stream = StreamOwned::new(session, socket);
+
+            let mut request = [0; 7];
+            stream.read_exact(&mut request).ok()?;
+            assert_eq!(&request, b"=3\tfoo\n");
+
+            stream.write_all(b"$3\tbar\n").unwrap();
+            stream.flush().unwrap();
+
+            Some(Peer {
+                server_name: stream.conn.server_name().map(String::from),
+                has_cert: stream.conn.peer_certificates().is_some(),
+            })
+        });
+
+        (port, handle)
+    }
+
+    fn server_config(server: &Identity) -> ServerConfig {
+        ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(load_certs(&server.cert).unwrap(), load_key(&server.key).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn get_over_tls() {
+        let server = identity("server");
+        let (port, handle) = serve(server_config(&server));
+
+        let mut connection = TlsConnection::builder("127.0.0.1", port)
+            .ca_file(&server.cert)
+            .server_name("localhost")
+            .read_timeout(Duration::from_secs(5))
+            .connect()
+            .unwrap();
+
+        connection.write(b"=3\tfoo\n").unwrap();
+        assert_eq!(connection.read().unwrap(), b"$3\tbar\n".to_vec());
+
+        assert_eq!(handle.join().unwrap(), Some(Peer { server_name: Some(String::from("localhost")), has_cert: false }));
+
+        server.remove();
+    }
+
+    #[test]
+    fn rejects_untrusted_certificate() {
+        let server = identity("untrusted");
+        let (port, handle) = serve(server_config(&server));
+
+        let result = TlsConnection::builder("127.0.0.1", port).server_name("localhost").connect();
+
+        assert!(matches!(result.err().unwrap().kind(), ErrorKind::Tls(_)));
+        assert_eq!(handle.join().unwrap(), None);
+
+        server.remove();
+    }
+
+    #[test]
+    fn authenticates_with_client_certificate() {
+        let server = identity("mtls-server");
+        let client = identity("mtls-client");
+
+        let mut roots = RootCertStore::empty();
+        roots.add_parsable_certificates(load_certs(&client.cert).unwrap());
+        let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider()).build().unwrap();
+
+        let config = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(load_certs(&server.cert).unwrap(), load_key(&server.key).unwrap())
+            .unwrap();
+        let (port, handle) = serve(config);
+
+        let info = ConnectionInfo::from_url(&format!(
+            "tamaris://127.0.0.1:{}/?cacert={}&cert={}&key={}&sni=localhost&timeout=5",
+            port,
+            server.cert.display(),
+            client.cert.display(),
+            client.key.display(),
+        )).unwrap();
+        let mut connection = TlsConnection::from_info(&info).unwrap();
+
+        connection.write(b"=3\tfoo\n").unwrap();
+        assert_eq!(connection.read().unwrap(), b"$3\tbar\n".to_vec());
+
+        assert_eq!(handle.join().unwrap(), Some(Peer { server_name: Some(String::from("localhost")), has_cert: true }));
+
+        server.remove();
+        client.remove();
+    }
+}
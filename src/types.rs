@@ -0,0 +1,260 @@
+use std::borrow::Cow;
+use std::str;
+use crate::error::{ErrorKind, ServerError, TamariError};
+use crate::parser::Response;
+
+/// Converts a Rust value into the bytes of a request argument, such as a key
+/// or a value.
+///
+/// Strings and byte buffers are written as they are. Numbers and bools are
+/// written in their decimal text form, the same form [`FromTamariValue`]
+/// parses them from.
+pub trait ToTamariArg {
+    /// Appends the argument's bytes to `out`.
+    fn write_tamari_arg(&self, out: &mut Vec<u8>);
+
+    fn to_tamari_arg(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_tamari_arg(&mut out);
+        out
+    }
+}
+
+impl<T: ToTamariArg + ?Sized> ToTamariArg for &T {
+    fn write_tamari_arg(&self, out: &mut Vec<u8>) {
+        (**self).write_tamari_arg(out)
+    }
+}
+
+impl ToTamariArg for [u8] {
+    fn write_tamari_arg(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self)
+    }
+}
+
+impl<const N: usize> ToTamariArg for [u8; N] {
+    fn write_tamari_arg(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self)
+    }
+}
+
+impl ToTamariArg for Vec<u8> {
+    fn write_tamari_arg(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self)
+    }
+}
+
+impl ToTamariArg for Box<[u8]> {
+    fn write_tamari_arg(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self)
+    }
+}
+
+impl ToTamariArg for Cow<'_, [u8]> {
+    fn write_tamari_arg(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self)
+    }
+}
+
+impl ToTamariArg for str {
+    fn write_tamari_arg(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes())
+    }
+}
+
+impl ToTamariArg for String {
+    fn write_tamari_arg(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes())
+    }
+}
+
+impl ToTamariArg for Box<str> {
+    fn write_tamari_arg(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes())
+    }
+}
+
+impl ToTamariArg for Cow<'_, str> {
+    fn write_tamari_arg(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes())
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl ToTamariArg for bytes::Bytes {
+    fn write_tamari_arg(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self)
+    }
+}
+
+macro_rules! to_tamari_arg_display {
+    ($($t:ty),*) => {
+        $(
+            impl ToTamariArg for $t {
+                fn write_tamari_arg(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(self.to_string().as_bytes())
+                }
+            }
+        )*
+    };
+}
+
+to_tamari_arg_display!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64, bool);
+
+/// Converts a response into a Rust value, the decoding counterpart of
+/// [`ToTamariArg`].
+///
+/// A `Response::NotFound` converts to `None` for `Option<T>` and fails with a
+/// `NOTFOUND` server error otherwise, and error responses become
+/// `ErrorKind::Server` errors. A success without a value is an empty value.
+/// Numbers and bools are parsed from their decimal text form.
+pub trait FromTamariValue: Sized {
+    fn from_tamari_value(response: Response) -> Result<Self, TamariError>;
+}
+
+impl FromTamariValue for Response {
+    fn from_tamari_value(response: Response) -> Result<Self, TamariError> {
+        Ok(response)
+    }
+}
+
+impl FromTamariValue for Vec<u8> {
+    fn from_tamari_value(response: Response) -> Result<Self, TamariError> {
+        value(response)
+    }
+}
+
+impl FromTamariValue for String {
+    fn from_tamari_value(response: Response) -> Result<Self, TamariError> {
+        match String::from_utf8(value(response)?) {
+            Ok(s) => Ok(s),
+            Err(_) => Err(conversion_error(String::from("value is not valid UTF-8"))),
+        }
+    }
+}
+
+impl FromTamariValue for bool {
+    fn from_tamari_value(response: Response) -> Result<Self, TamariError> {
+        match &value(response)?[..] {
+            b"true" | b"1" => Ok(true),
+            b"false" | b"0" => Ok(false),
+            other => Err(conversion_error(format!("cannot convert \"{}\" to bool", String::from_utf8_lossy(other)))),
+        }
+    }
+}
+
+impl<T: FromTamariValue> FromTamariValue for Option<T> {
+    fn from_tamari_value(response: Response) -> Result<Self, TamariError> {
+        match response {
+            Response::NotFound => Ok(None),
+            response => T::from_tamari_value(response).map(Some),
+        }
+    }
+}
+
+macro_rules! from_tamari_value_parse {
+    ($($t:ty),*) => {
+        $(
+            impl FromTamariValue for $t {
+                fn from_tamari_value(response: Response) -> Result<Self, TamariError> {
+                    let value = value(response)?;
+
+                    match str::from_utf8(&value).ok().and_then(|s| s.parse::<$t>().ok()) {
+                        Some(n) => Ok(n),
+                        None => Err(conversion_error(format!(
+                            "cannot convert \"{}\" to {}",
+                            String::from_utf8_lossy(&value),
+                            stringify!($t),
+                        ))),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+from_tamari_value_parse!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+
+fn value(response: Response) -> Result<Vec<u8>, TamariError> {
+    match response {
+        Response::SuccessWithResult(value) => Ok(value),
+        Response::Success => Ok(Vec::new()),
+        Response::Multi(values) => Err(conversion_error(format!("expected a single value, got {}", values.len()))),
+        Response::NotFound => Err(TamariError::new(ErrorKind::Server(ServerError::new("NOTFOUND", "key not found")))),
+        Response::Error(msg) => Err(TamariError::new(ErrorKind::Server(ServerError::parse(&msg)))),
+    }
+}
+
+fn conversion_error(msg: String) -> TamariError {
+    TamariError::new(ErrorKind::Conversion(msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(value: &str) -> Response {
+        Response::SuccessWithResult(value.as_bytes().to_vec())
+    }
+
+    fn is_conversion_error<T>(result: Result<T, TamariError>) -> bool {
+        matches!(result, Err(ref e) if matches!(e.kind(), ErrorKind::Conversion(_)))
+    }
+
+    #[test]
+    fn write_args() {
+        assert_eq!("foo".to_tamari_arg(), b"foo");
+        assert_eq!(String::from("foo").to_tamari_arg(), b"foo");
+        assert_eq!(b"foo".to_tamari_arg(), b"foo");
+        assert_eq!(vec![0xffu8].into_boxed_slice().to_tamari_arg(), vec![0xff]);
+        assert_eq!(Cow::Borrowed(&b"foo"[..]).to_tamari_arg(), b"foo");
+        assert_eq!(Cow::<str>::Owned(String::from("foo")).to_tamari_arg(), b"foo");
+        assert_eq!(42u64.to_tamari_arg(), b"42");
+        assert_eq!((-7i8).to_tamari_arg(), b"-7");
+        assert_eq!(2.5f64.to_tamari_arg(), b"2.5");
+        assert_eq!(true.to_tamari_arg(), b"true");
+    }
+
+    #[test]
+    fn write_args_appends() {
+        let mut out = b"=".to_vec();
+        "foo".write_tamari_arg(&mut out);
+        (&&17u32).write_tamari_arg(&mut out);
+
+        assert_eq!(out, b"=foo17");
+    }
+
+    #[test]
+    fn convert_numbers() {
+        assert_eq!(u64::from_tamari_value(result("42")).unwrap(), 42);
+        assert_eq!(i8::from_tamari_value(result("-7")).unwrap(), -7);
+        assert_eq!(f64::from_tamari_value(result("2.5")).unwrap(), 2.5);
+
+        assert!(is_conversion_error(u8::from_tamari_value(result("256"))));
+        assert!(is_conversion_error(u64::from_tamari_value(result("-1"))));
+        assert!(is_conversion_error(i32::from_tamari_value(result("forty"))));
+    }
+
+    #[test]
+    fn convert_strings_and_bools() {
+        assert_eq!(String::from_tamari_value(result("bar")).unwrap(), "bar");
+        assert_eq!(Vec::<u8>::from_tamari_value(Response::SuccessWithResult(vec![0xff])).unwrap(), vec![0xff]);
+        assert!(bool::from_tamari_value(result("true")).unwrap());
+        assert!(!bool::from_tamari_value(result("0")).unwrap());
+
+        assert!(is_conversion_error(String::from_tamari_value(Response::SuccessWithResult(vec![0xff]))));
+        assert!(is_conversion_error(bool::from_tamari_value(result("maybe"))));
+    }
+
+    #[test]
+    fn convert_missing_values() {
+        assert_eq!(Option::<u64>::from_tamari_value(Response::NotFound).unwrap(), None);
+        assert_eq!(Option::<u64>::from_tamari_value(result("7")).unwrap(), Some(7));
+        assert_eq!(Option::<String>::from_tamari_value(Response::Success).unwrap(), Some(String::new()));
+
+        assert!(u64::from_tamari_value(Response::NotFound).unwrap_err().is_not_found());
+        assert!(is_conversion_error(u64::from_tamari_value(Response::Success)));
+        let result = Option::<u64>::from_tamari_value(Response::Error(b"NOAUTH".to_vec()));
+        assert!(result.unwrap_err().is_auth_error());
+    }
+}
@@ -0,0 +1,201 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use tamari::testing::FakeServer;
+
+// Runs the CLI against `server`, isolated from the environment's settings.
+fn run(server: &FakeServer, args: &[&str], stdin: &str) -> Output {
+    let url = server.url();
+    run_with(&[], &[&["--url", &url], args].concat(), stdin)
+}
+
+// Every variable the CLI reads its settings from.
+const ENV_SETTINGS: &[&str] = &[
+    "TAMARI_URL", "TAMARI_CLI_CONFIG", "TAMARI_CLI_PROFILE", "TAMARI_CLI_ADDRESS", "TAMARI_CLI_PORT", "TAMARI_CLI_SOCKET",
+    "TAMARI_CLI_PASSWORD", "TAMARI_CLI_TIMEOUT", "TAMARI_CLI_CONNECT_TIMEOUT", "TAMARI_CLI_FORMAT", "TAMARI_CLI_TLS",
+    "TAMARI_CLI_CACERT", "TAMARI_CLI_CERT", "TAMARI_CLI_KEY",
+];
+
+// Runs the CLI with only the settings in `env` taken from the environment.
+fn run_with(env: &[(&str, &str)], args: &[&str], stdin: &str) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_tamari-cli"));
+    for name in ENV_SETTINGS {
+        command.env_remove(name);
+    }
+
+    let mut child = command
+        .args(args)
+        .envs(env.iter().copied())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> &str {
+    std::str::from_utf8(&output.stdout).unwrap()
+}
+
+#[test]
+fn set_and_get() {
+    let server = FakeServer::tcp().unwrap();
+
+    let output = run(&server, &["set", "foo", "bar"], "");
+    assert!(output.status.success());
+    assert_eq!(server.get("foo"), Some(b"bar".to_vec()));
+
+    let output = run(&server, &["get", "foo"], "");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "\"bar\"\n");
+}
+
+#[test]
+fn get_missing_key_exits_with_1() {
+    let server = FakeServer::tcp().unwrap();
+
+    let output = run(&server, &["--format", "raw", "get", "foo"], "");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "");
+}
+
+#[test]
+fn mget_prints_responses_in_order() {
+    let server = FakeServer::tcp().unwrap();
+    server.insert("a", "1");
+    server.insert("c", "3");
+
+    let output = run(&server, &["mget", "a", "b", "c"], "");
+
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "\"1\"\nNot found\n\"3\"\n");
+}
+
+#[test]
+fn pipeline_from_stdin() {
+    let server = FakeServer::tcp().unwrap();
+
+    let output = run(&server, &["--pipeline", "2", "--format", "raw"], "set a 1\nset b 2\nget a\nmget b a\n");
+
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "1\n2\n1\n");
+    assert_eq!(server.get("b"), Some(b"2".to_vec()));
+}
+
+#[test]
+fn repl_reads_until_eof() {
+    let server = FakeServer::tcp().unwrap();
+
+    let output = run(&server, &[], "set foo bar\nget foo\n");
+
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "tamari> Ok\ntamari> \"bar\"\ntamari> ");
+}
+
+#[test]
+fn url_settings_beat_environment() {
+    let server = FakeServer::tcp().unwrap();
+    server.set_password(Some("secret"));
+    server.insert("foo", "bar");
+    let url = format!("{}/?timeout=5", server.url().replace("tamari://", "tamari://:secret@"));
+
+    let output = run_with(&[("TAMARI_CLI_PASSWORD", "wrong"), ("TAMARI_CLI_TIMEOUT", "x")], &["--url", &url, "get", "foo"], "");
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(stdout(&output), "\"bar\"\n");
+}
+
+#[test]
+fn environment_address_beats_config_socket() {
+    let path = std::env::temp_dir().join(format!("tamari-cli-{}.conf", std::process::id()));
+    std::fs::write(&path, "socket = /nonexistent/tamari.sock\n").unwrap();
+    let server = FakeServer::tcp().unwrap();
+    server.insert("foo", "bar");
+    let port = server.url().rsplit(':').next().unwrap().to_string();
+
+    let env = [("TAMARI_CLI_CONFIG", path.to_str().unwrap()), ("TAMARI_CLI_ADDRESS", "127.0.0.1"), ("TAMARI_CLI_PORT", &port)];
+    let output = run_with(&env, &["get", "foo"], "");
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(stdout(&output), "\"bar\"\n");
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn config_certificates_keep_plain_url() {
+    let path = std::env::temp_dir().join(format!("tamari-cli-{}-tls.conf", std::process::id()));
+    std::fs::write(&path, "cacert = /nonexistent/ca.pem\n").unwrap();
+    let server = FakeServer::tcp().unwrap();
+    server.insert("foo", "bar");
+
+    let output = run_with(&[("TAMARI_CLI_CONFIG", path.to_str().unwrap())], &["--url", &server.url(), "get", "foo"], "");
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(stdout(&output), "\"bar\"\n");
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn socket_conflicts_with_url() {
+    let server = FakeServer::tcp().unwrap();
+
+    let output = run(&server, &["--socket", "/nonexistent/tamari.sock", "get", "foo"], "");
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be used with"), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn replays_recorded_session() {
+    let path = std::env::temp_dir().join(format!("tamari-cli-{}.jsonl", std::process::id()));
+    let path = path.to_str().unwrap();
+    let server = FakeServer::tcp().unwrap();
+    server.insert("foo", "bar");
+
+    let recorded = run(&server, &["--record", path, "mget", "foo", "baz"], "");
+    assert!(recorded.status.success());
+
+    // the responses come from the recording, not from the emptied server
+    drop(server);
+    let server = FakeServer::tcp().unwrap();
+
+    let replayed = run(&server, &["--replay", path, "mget", "foo", "baz"], "");
+    assert!(replayed.status.success());
+    assert_eq!(stdout(&replayed), stdout(&recorded));
+
+    let diverged = run(&server, &["--replay", path, "get", "foo"], "");
+    assert!(!diverged.status.success());
+    assert!(std::str::from_utf8(&diverged.stderr).unwrap().contains("Replay error: write of \"=3\\tfoo\\n\" diverges"));
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn debug_replies_are_scripted() {
+    let server = FakeServer::tcp().unwrap();
+
+    let output = run(&server, &["--debug", "--debug-reply", "$3\\tbar\\n", "--debug-reply", "?\\n", "mget", "foo", "baz"], "");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("  00000000  3d 33 09 66 6f 6f 0a                              |=3.foo.|\n"));
+    assert!(stdout(&output).contains("  get (=): key, 3 bytes: \"baz\"\n"));
+    assert!(stdout(&output).ends_with("  not found (?)\n\"bar\"\nNot found\n"));
+}
+
+#[test]
+fn repl_prints_stats() {
+    let server = FakeServer::tcp().unwrap();
+
+    let output = run(&server, &[], "get foo\nset foo bar\nstats\nstats prometheus\n");
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("tamari> get: 1 requests, 0 errors, p50 "));
+    assert!(stdout(&output).contains("\nset: 1 requests, 0 errors, p50 "));
+    assert!(stdout(&output).contains("\ndelete: 0 requests\ntamari> # HELP tamari_client_requests_total "));
+    assert!(stdout(&output).contains("\ntamari_client_request_duration_seconds_count{op=\"set\"} 1\n"));
+}
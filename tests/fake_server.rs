@@ -0,0 +1,158 @@
+use std::time::Duration;
+use tamari::testing::{FakeServer, Fault};
+use tamari::{Client, Connection, ErrorKind, ParseError, Response, RetryPolicy, TcpConnection};
+
+#[test]
+fn tcp_connection_round_trip() {
+    let server = FakeServer::tcp().unwrap();
+    let mut connection = TcpConnection::from_info(server.info()).unwrap();
+
+    connection.write(b"+3\tfoo3\tbar\n").unwrap();
+    assert_eq!(connection.read().unwrap(), b"$\n".to_vec());
+
+    connection.write(b"=3\tfoo\n=3\tbaz\n").unwrap();
+    assert_eq!(connection.read().unwrap(), b"$3\tbar\n".to_vec());
+    assert_eq!(connection.read().unwrap(), b"?\n".to_vec());
+}
+
+#[test]
+fn client_set_get_delete() {
+    let server = FakeServer::tcp().unwrap();
+    let mut client = Client::from_url(&server.url()).unwrap();
+
+    assert_eq!(client.set("foo", 42).unwrap(), Response::Success);
+    assert_eq!(client.get_as::<u64>("foo").unwrap(), 42);
+    assert_eq!(server.get("foo"), Some(b"42".to_vec()));
+
+    assert_eq!(client.delete("foo").unwrap(), Response::Success);
+    assert_eq!(client.get_opt("foo").unwrap(), None);
+}
+
+#[test]
+fn client_pipeline() {
+    let server = FakeServer::tcp().unwrap();
+    server.insert("a", "1");
+    let mut client = Client::from_url(&server.url()).unwrap();
+
+    let responses: Vec<Response> = client.pipeline().set("b", "2").get("a").get("b").get("c").execute()
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+
+    assert_eq!(responses, vec![
+        Response::Success,
+        Response::SuccessWithResult(b"1".to_vec()),
+        Response::SuccessWithResult(b"2".to_vec()),
+        Response::NotFound,
+    ]);
+}
+
+#[test]
+fn pipeline_keeps_going_after_parse_error() {
+    let server = FakeServer::tcp().unwrap();
+    server.insert("b", "b");
+    server.inject(Fault::Raw(b"!\n".to_vec()));
+
+    let mut client = Client::from_url(&server.url()).unwrap();
+    let responses = client.pipeline().get("a").get("b").execute();
+
+    assert!(matches!(responses[0], Err(ref e) if matches!(e.kind(), ErrorKind::Parse(ParseError::MissingArgument))));
+    assert_eq!(responses[1].as_ref().unwrap(), &Response::SuccessWithResult(b"b".to_vec()));
+}
+
+#[test]
+fn pipeline_aborts_after_framing_error() {
+    let server = FakeServer::tcp().unwrap();
+    server.inject(Fault::Corrupt);
+
+    let mut client = Client::from_url(&server.url()).unwrap();
+    let responses = client.pipeline().get("a").get("b").execute();
+
+    assert!(matches!(responses[0], Err(ref e) if matches!(e.kind(), ErrorKind::Parse(ParseError::InvalidPrefix(_)))));
+    assert!(matches!(responses[1], Err(ref e) if matches!(e.kind(), ErrorKind::IO(e) if e.kind() == std::io::ErrorKind::ConnectionAborted)));
+}
+
+#[test]
+fn client_authenticates() {
+    let server = FakeServer::tcp().unwrap();
+    server.set_password(Some("hunter2"));
+
+    let mut client = Client::from_url(&server.url()).unwrap();
+    client.set_strict_errors(true);
+    assert!(client.get("foo").unwrap_err().is_auth_error());
+
+    let url = server.url().replacen("tamari://", "tamari://:hunter2@", 1);
+    let mut client = Client::from_url(&url).unwrap();
+    assert_eq!(client.get("foo").unwrap(), Response::NotFound);
+
+    let url = server.url().replacen("tamari://", "tamari://:wrong@", 1);
+    assert!(Client::from_url(&url).err().unwrap().is_auth_error());
+}
+
+#[test]
+fn retries_dropped_connection() {
+    let server = FakeServer::tcp().unwrap();
+    server.insert("foo", "bar");
+    server.inject(Fault::Drop);
+
+    let mut client = Client::from_url(&server.url()).unwrap();
+    client.set_retry_policy(RetryPolicy::new(2).base_delay(Duration::from_millis(1)));
+
+    assert_eq!(client.get("foo").unwrap(), Response::SuccessWithResult(b"bar".to_vec()));
+}
+
+#[test]
+fn reports_corrupt_frames_and_server_errors() {
+    let server = FakeServer::tcp().unwrap();
+    server.inject(Fault::Corrupt);
+    server.inject(Fault::Error(String::from("BUSY try again")));
+
+    let mut client = Client::from_url(&server.url()).unwrap();
+
+    assert!(matches!(client.get("foo").unwrap_err().kind(), ErrorKind::Parse(_)));
+    assert_eq!(client.get("foo").unwrap(), Response::Error(b"BUSY try again".to_vec()));
+    assert_eq!(client.get("foo").unwrap(), Response::NotFound);
+}
+
+#[test]
+fn times_out_on_latency() {
+    let server = FakeServer::tcp().unwrap();
+    server.inject(Fault::Latency(Duration::from_millis(500)));
+
+    let mut client = Client::from_url(&format!("{}/?timeout=0.05", server.url())).unwrap();
+
+    assert!(matches!(client.get("foo").unwrap_err().kind(), ErrorKind::Timeout(_)));
+}
+
+#[test]
+fn timed_out_connection_is_not_reused() {
+    let server = FakeServer::tcp().unwrap();
+    server.insert("aaa", "first");
+    server.insert("bbb", "second");
+    server.inject(Fault::Latency(Duration::from_millis(200)));
+
+    let mut client = Client::from_url(&format!("{}/?timeout=0.05", server.url())).unwrap();
+
+    assert!(matches!(client.get("aaa").unwrap_err().kind(), ErrorKind::Timeout(_)));
+    std::thread::sleep(Duration::from_millis(250));
+    assert!(matches!(client.get("bbb").unwrap_err().kind(), ErrorKind::IO(_)));
+
+    client.reconnect().unwrap();
+    assert_eq!(client.get("bbb").unwrap(), Response::SuccessWithResult(b"second".to_vec()));
+}
+
+#[cfg(unix)]
+#[test]
+fn unix_socket_round_trip() {
+    let path = std::env::temp_dir().join(format!("tamari-fake-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let server = FakeServer::unix(&path).unwrap();
+
+    let mut client = Client::from_url(&server.url()).unwrap();
+    client.set("foo", "bar").unwrap();
+
+    assert_eq!(client.get_as::<String>("foo").unwrap(), "bar");
+
+    drop(server);
+    assert!(!path.exists());
+}